@@ -0,0 +1,227 @@
+use std::path::PathBuf;
+
+use crate::colour::Colouring;
+use crate::complex::Complex;
+use crate::location::Location;
+use crate::palette::parse_rgb;
+use crate::palette_file;
+use crate::render::RenderSettings;
+use crate::screen::ScreenInfo;
+
+pub const USAGE: &str = "\
+usage: mandelbrod [options]
+
+view:
+  --center <re>,<im>          centre of the view (default depends on the fractal)
+  --zoom <factor>             magnification relative to the default view (default 1)
+  --bounds <x0>,<x1>,<y0>,<y1>
+                              explicit view bounds, overrides --center/--zoom
+  --location <file>           load the view and settings from a screenshot sidecar
+
+fractal:
+  --fractal <name>|<formula>  mandelbrot, burning-ship, tricorn, celtic, buffalo or perpendicular
+                              (default mandelbrot), or an iteration step such as `z^3 - z + c`, in z,
+                              c, pixel, i, pi and e with + - * / ^ and sin, cos, tan, sinh, cosh,
+                              tanh, exp, log, sqrt, abs, conj, re and im; `<start>; <step>` also sets
+                              where orbits start, as in `pixel; sin(z) * c`
+  --exponent <n>|<re>,<im>    power z is raised to each step (default 2); real and complex exponents
+                              work too, as long as the real part is at least 1.1
+  --julia <re>,<im>           render the Julia set for this c instead
+
+output:
+  --width <px>                image / window width (default 1000)
+  --height <px>               image / window height (default 800, or from the bounds' aspect ratio)
+  --output <file>             png to write; in the viewer, where the S key saves to
+  --headless                  render straight to --output (default mandelbrod.png) without a window
+
+render:
+  --iters <n>                 maximum iterations per point (default 10000)
+  --step <px>                 sample every n-th pixel (default 1)
+  --threads <n>               worker threads, 0 for one per core (default 0); the viewer's Julia
+                              preview has two more of its own
+  --no-periodicity            iterate interior points to the limit instead of stopping at detected cycles
+
+colour:
+  --colouring <mode>          smooth, banded, histogram, distance, lighting or trap (default smooth)
+                              distance and lighting need the mandelbrot formula, at any exponent
+  --palette <palette>         built-in name (grey, ultra, fire, ocean, rainbow, twilight), evenly
+                              spaced colours `#000000,#ff8800,#ffffff`, stops `0:#000000,0.3:#ff8800,1:#ffffff`
+                              or a GIMP .ggr / .gpl or Fractint .map file
+  --palette-scale <n>         how many times the palette repeats (default 1)
+  --palette-offset <t>        shift into the palette, 0..1 (default 0)
+  --interior <#rrggbb>        colour of points inside the set (default #000000)
+  --interior-colouring <mode> flat, period, magnitude, min-magnitude, angle or trap (default flat)
+  --light-angle <degrees>     direction of the light for the lighting mode (default 45)
+  --light-height <h>          height of the light above the surface (default 1.5)
+  --trap <trap>               orbit trap for the trap modes: point:x,y, line:x,y,angle, cross:x,y,angle
+                              or circle:x,y,radius (default point:0,0)
+
+  -h, --help                  show this message";
+
+const DEFAULT_WIDTH: i32 = 1000;
+const DEFAULT_HEIGHT: i32 = 800;
+// Smallest real part `--exponent` accepts.
+pub const MIN_EXPONENT: f64 = 1.1;
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub screen: ScreenInfo,
+    pub settings: RenderSettings,
+    pub threads: usize,
+    pub colouring: Colouring,
+    pub output: Option<PathBuf>,
+    pub headless: bool,
+    pub help: bool,
+}
+
+impl Options {
+    pub fn output_or_default(&self) -> PathBuf {
+        self.output.clone().unwrap_or_else(|| PathBuf::from("mandelbrod.png"))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{}: `{}` is not a valid number", flag, value))
+}
+
+fn parse_list(flag: &str, value: &str, len: usize) -> Result<Vec<f64>, String> {
+    let values = value
+        .split(',')
+        .map(|v| parse_number(flag, v))
+        .collect::<Result<Vec<f64>, String>>()?;
+
+    if values.len() != len {
+        return Err(format!("{}: expected {} comma separated numbers, got `{}`", flag, len, value));
+    }
+    Ok(values)
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut center = None;
+    let mut zoom = 1.0;
+    let mut bounds: Option<(f64, f64, f64, f64)> = None;
+    let mut width: Option<i32> = None;
+    let mut height: Option<i32> = None;
+    let mut settings = RenderSettings::default();
+    let mut threads = 0;
+    let mut colouring = Colouring::default();
+    let mut output = None;
+    let mut headless = false;
+    let mut help = false;
+
+    let mut args = args.into_iter();
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} expects a value", flag));
+
+        match flag.as_str() {
+            "--center" => {
+                let v = parse_list(&flag, &value()?, 2)?;
+                center = Some(Complex::new(v[0], v[1]));
+            }
+            "--zoom" => zoom = parse_number(&flag, &value()?)?,
+            "--bounds" => {
+                let v = parse_list(&flag, &value()?, 4)?;
+                bounds = Some((v[0], v[1], v[2], v[3]));
+            }
+            "--location" => {
+                let path = value()?;
+                let location = Location::load(&path).map_err(|e| format!("{}: {}", path, e))?;
+                bounds = Some((location.x_start, location.x_stop, location.y_start, location.y_stop));
+                width = Some(location.width);
+                height = Some(location.height);
+                settings.iters = location.iters;
+                settings.accuracy = location.accuracy;
+                settings.periodicity = location.periodicity;
+                settings.fractal = location.fractal;
+                settings.exponent = location.exponent;
+                settings.julia = location.julia;
+                colouring = location.colouring;
+            }
+            "--fractal" => settings.fractal = value()?.parse().map_err(|e| format!("{}: {}", flag, e))?,
+            "--exponent" => settings.exponent = value()?.parse().map_err(|e| format!("{}: {}", flag, e))?,
+            "--julia" => {
+                let v = parse_list(&flag, &value()?, 2)?;
+                settings.julia = Some(Complex::new(v[0], v[1]));
+            }
+            "--width" => width = Some(parse_number(&flag, &value()?)?),
+            "--height" => height = Some(parse_number(&flag, &value()?)?),
+            "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+            "--headless" => headless = true,
+            "--iters" => settings.iters = parse_number(&flag, &value()?)?,
+            "--step" => settings.accuracy = parse_number(&flag, &value()?)?,
+            "--no-periodicity" => settings.periodicity = false,
+            "--threads" => threads = parse_number(&flag, &value()?)?,
+            "--colouring" => colouring.mode = value()?.parse().map_err(|e| format!("{}: {}", flag, e))?,
+            "--palette" => {
+                let value = value()?;
+                colouring.palette = if palette_file::is_palette_file(&value) {
+                    palette_file::load(&value).map_err(|e| format!("{}: {}", value, e))?
+                } else {
+                    value.parse().map_err(|e| format!("{}: {}", flag, e))?
+                };
+            }
+            "--palette-scale" => colouring.scale = parse_number(&flag, &value()?)?,
+            "--palette-offset" => colouring.offset = parse_number(&flag, &value()?)?,
+            "--interior" => colouring.interior = parse_rgb(&value()?).map_err(|e| format!("{}: {}", flag, e))?,
+            "--interior-colouring" => {
+                colouring.interior_mode = value()?.parse().map_err(|e| format!("{}: {}", flag, e))?
+            }
+            "--light-angle" => colouring.light.angle = parse_number(&flag, &value()?)?,
+            "--light-height" => colouring.light.height = parse_number(&flag, &value()?)?,
+            "--trap" => colouring.trap = value()?.parse().map_err(|e| format!("{}: {}", flag, e))?,
+            "--help" | "-h" => help = true,
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+
+    if zoom <= 0.0 {
+        return Err("--zoom must be positive".to_string());
+    }
+    // Closer to 1, orbits grow so slowly that hardly any point escapes within the iteration limit.
+    if settings.exponent.real < MIN_EXPONENT {
+        return Err(format!("--exponent must have a real part of at least {}", MIN_EXPONENT));
+    }
+    if settings.iters < 1 || settings.accuracy < 1 {
+        return Err("--iters and --step must be at least 1".to_string());
+    }
+    if colouring.needs_derivative() && !settings.fractal.has_derivative() {
+        return Err(format!(
+            "--colouring {} needs the derivative of the formula, which `{}` does not have",
+            colouring.mode, settings.fractal
+        ));
+    }
+
+    let width = width.unwrap_or(DEFAULT_WIDTH);
+    let screen = match bounds {
+        Some((x_start, x_stop, y_start, y_stop)) => {
+            if x_stop <= x_start || y_stop <= y_start {
+                return Err("view bounds must satisfy x0 < x1 and y0 < y1".to_string());
+            }
+            let height =
+                height.unwrap_or_else(|| (width as f64 * (y_stop - y_start) / (x_stop - x_start)).round() as i32);
+            ScreenInfo::from((x_start, x_stop, y_start, y_stop, 1.0)).with_size(width, height)
+        }
+        None => {
+            let (default_center, view_width) = settings.default_view();
+            let center = center.unwrap_or(default_center);
+            ScreenInfo::centered(center, view_width / zoom, width, height.unwrap_or(DEFAULT_HEIGHT))
+        }
+    };
+
+    if screen.screen_width < 1 || screen.screen_height < 1 {
+        return Err("--width and --height must be at least 1".to_string());
+    }
+
+    Ok(Options {
+        screen,
+        settings,
+        threads,
+        colouring,
+        output,
+        headless,
+        help,
+    })
+}
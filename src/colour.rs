@@ -0,0 +1,358 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::complex::Complex;
+use crate::formula::Fractal;
+use crate::palette::{Palette, Rgb};
+use crate::render::{Pixel, RenderSettings};
+use crate::trap::Trap;
+
+// Distance in screen pixels over which the distance shading goes from the palette start to its end.
+const DISTANCE_FALLOFF: f64 = 4.0;
+// Share of the palette colour that stays visible on faces turned away from the light.
+const AMBIENT: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColourMode {
+    // Integer escape counts, the original look with visible bands.
+    Banded,
+    // Fractional escape counts, no banding.
+    #[default]
+    Smooth,
+    // Smooth counts ranked against every other pixel of the image, so the palette is spread over
+    // however narrow a range of escape counts the view actually contains.
+    Histogram,
+    // Estimated distance to the boundary: dark filaments and outlines right at the set, fading
+    // out over a few pixels. Does not depend on the iteration count.
+    Distance,
+    // Smooth colouring lit like an embossed surface, using the orbit derivative for normals.
+    Lighting,
+    // Closest approach of the orbit to the orbit trap.
+    Trap,
+}
+
+impl ColourMode {
+    pub fn next(self) -> ColourMode {
+        match self {
+            ColourMode::Banded => ColourMode::Smooth,
+            ColourMode::Smooth => ColourMode::Histogram,
+            ColourMode::Histogram => ColourMode::Distance,
+            ColourMode::Distance => ColourMode::Lighting,
+            ColourMode::Lighting => ColourMode::Trap,
+            ColourMode::Trap => ColourMode::Banded,
+        }
+    }
+}
+
+impl fmt::Display for ColourMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ColourMode::Banded => "banded",
+            ColourMode::Smooth => "smooth",
+            ColourMode::Histogram => "histogram",
+            ColourMode::Distance => "distance",
+            ColourMode::Lighting => "lighting",
+            ColourMode::Trap => "trap",
+        })
+    }
+}
+
+impl FromStr for ColourMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "banded" => Ok(ColourMode::Banded),
+            "smooth" => Ok(ColourMode::Smooth),
+            "histogram" => Ok(ColourMode::Histogram),
+            "distance" => Ok(ColourMode::Distance),
+            "lighting" => Ok(ColourMode::Lighting),
+            "trap" => Ok(ColourMode::Trap),
+            _ => Err(format!(
+                "unknown colouring mode `{}` (expected banded, smooth, histogram, distance, lighting or trap)",
+                s
+            )),
+        }
+    }
+}
+
+// What the inside of the set is coloured by. Everything but `Flat` goes through the palette.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InteriorMode {
+    // The single interior colour.
+    #[default]
+    Flat,
+    // Length of the detected attracting cycle.
+    Period,
+    // |z| where the orbit ended up.
+    Magnitude,
+    // Closest approach of the orbit to the origin.
+    MinMagnitude,
+    // Argument of the final z.
+    Angle,
+    // Closest approach of the orbit to the orbit trap.
+    Trap,
+}
+
+impl InteriorMode {
+    pub fn next(self) -> InteriorMode {
+        match self {
+            InteriorMode::Flat => InteriorMode::Period,
+            InteriorMode::Period => InteriorMode::Magnitude,
+            InteriorMode::Magnitude => InteriorMode::MinMagnitude,
+            InteriorMode::MinMagnitude => InteriorMode::Angle,
+            InteriorMode::Angle => InteriorMode::Trap,
+            InteriorMode::Trap => InteriorMode::Flat,
+        }
+    }
+}
+
+impl fmt::Display for InteriorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            InteriorMode::Flat => "flat",
+            InteriorMode::Period => "period",
+            InteriorMode::Magnitude => "magnitude",
+            InteriorMode::MinMagnitude => "min-magnitude",
+            InteriorMode::Angle => "angle",
+            InteriorMode::Trap => "trap",
+        })
+    }
+}
+
+impl FromStr for InteriorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flat" => Ok(InteriorMode::Flat),
+            "period" => Ok(InteriorMode::Period),
+            "magnitude" => Ok(InteriorMode::Magnitude),
+            "min-magnitude" => Ok(InteriorMode::MinMagnitude),
+            "angle" => Ok(InteriorMode::Angle),
+            "trap" => Ok(InteriorMode::Trap),
+            _ => Err(format!(
+                "unknown interior mode `{}` (expected flat, period, magnitude, min-magnitude, angle or trap)",
+                s
+            )),
+        }
+    }
+}
+
+// How computed pixels are turned into colours. Changing it never requires a new render.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colouring {
+    pub mode: ColourMode,
+    pub palette: Palette,
+    // How many times the palette repeats over the full range, and where in it the range starts.
+    pub scale: f64,
+    pub offset: f64,
+    pub interior: Rgb,
+    pub interior_mode: InteriorMode,
+    pub light: Light,
+    // Used by the trap modes; the renderer only tracks it while one of them is selected.
+    pub trap: Trap,
+}
+
+// Light for the lighting mode: `angle` in degrees around the screen, `height` above it
+// (0 grazes the surface, larger values flatten the relief).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub angle: f64,
+    pub height: f64,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Light {
+            angle: 45.0,
+            height: 1.5,
+        }
+    }
+}
+
+impl Light {
+    // Lambert factor in [0, 1] for a surface with the given unit normal.
+    pub fn shade(&self, normal: Complex) -> f64 {
+        let angle = self.angle.to_radians();
+        let facing = normal.real * angle.cos() + normal.imag * angle.sin();
+        ((facing + self.height) / (1.0 + self.height)).clamp(0.0, 1.0)
+    }
+}
+
+impl Default for Colouring {
+    fn default() -> Self {
+        Colouring {
+            mode: ColourMode::default(),
+            palette: Palette::default(),
+            scale: 1.0,
+            offset: 0.0,
+            interior: [0, 0, 0],
+            interior_mode: InteriorMode::default(),
+            light: Light::default(),
+            trap: Trap::default(),
+        }
+    }
+}
+
+impl Colouring {
+    // Whether colours depend on statistics over the whole image rather than on each pixel alone.
+    pub fn needs_statistics(&self) -> bool {
+        self.mode == ColourMode::Histogram
+    }
+
+    // Whether colours are computed from the orbit derivative.
+    pub fn needs_derivative(&self) -> bool {
+        matches!(self.mode, ColourMode::Distance | ColourMode::Lighting)
+    }
+
+    // This colouring as it can be shown for `fractal`: without a derivative, distance and lighting
+    // fall back to smooth colouring.
+    pub fn supported(&self, fractal: &Fractal) -> Colouring {
+        let mut colouring = self.clone();
+        if self.needs_derivative() && !fractal.has_derivative() {
+            colouring.mode = ColourMode::Smooth;
+        }
+        colouring
+    }
+
+    // Render settings with whatever extra per-pixel data this colouring reads switched on.
+    pub fn adjust(&self, settings: &RenderSettings) -> RenderSettings {
+        let derivative = self.needs_derivative();
+        let orbit_stats = !matches!(self.interior_mode, InteriorMode::Flat | InteriorMode::Period);
+        let uses_trap = self.mode == ColourMode::Trap || self.interior_mode == InteriorMode::Trap;
+        RenderSettings {
+            derivative: settings.derivative || derivative,
+            orbit_stats: settings.orbit_stats || orbit_stats,
+            trap: if uses_trap { Some(self.trap) } else { settings.trap },
+            ..settings.clone()
+        }
+    }
+
+    // Wraps `t * scale + offset` back into [0, 1]; exactly 1 stays 1 so the unscaled palette reaches its end.
+    fn cycle(&self, t: f64) -> f64 {
+        let u = t.clamp(0.0, 1.0) * self.scale + self.offset;
+        let wrapped = u.rem_euclid(1.0);
+        if wrapped == 0.0 && u > 0.0 {
+            1.0
+        } else {
+            wrapped
+        }
+    }
+}
+
+// Orbits that touch the trap map to the start of the palette, distant ones towards its end.
+fn trap_shade(distance: f64) -> f64 {
+    distance.sqrt().min(1.0)
+}
+
+// Cumulative distribution of escape counts over the escaped pixels of an image.
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    cdf: Vec<f64>,
+}
+
+impl Histogram {
+    pub fn new<'p>(pixels: impl IntoIterator<Item = &'p Pixel>, iters: i32) -> Self {
+        let mut counts = vec![0u64; iters.max(1) as usize + 1];
+        for p in pixels {
+            if p.escaped {
+                counts[p.escapes.min(iters) as usize] += 1;
+            }
+        }
+
+        let total = counts.iter().sum::<u64>().max(1) as f64;
+        let mut running = 0;
+        let cdf = counts
+            .iter()
+            .map(|&count| {
+                running += count;
+                running as f64 / total
+            })
+            .collect();
+
+        Histogram { cdf }
+    }
+
+    // Share of escaped pixels with a lower count, interpolated between neighbouring bins for smooth counts.
+    pub fn rank(&self, escapes: f64) -> f64 {
+        let last = self.cdf.len() - 1;
+        let n = (escapes.floor().max(1.0) as usize).min(last);
+        let below = self.cdf[n - 1];
+        let at = self.cdf[n];
+        below + (at - below) * (escapes - n as f64).clamp(0.0, 1.0)
+    }
+}
+
+// A colouring bound to one render: it knows the iteration limit and degree and, for modes that
+// look at the whole image, the statistics gathered from its pixels.
+pub struct Colourizer<'a> {
+    colouring: &'a Colouring,
+    iters: i32,
+    degree: f64,
+    histogram: Option<Histogram>,
+}
+
+impl<'a> Colourizer<'a> {
+    pub fn new<'p>(
+        colouring: &'a Colouring,
+        settings: &RenderSettings,
+        pixels: impl IntoIterator<Item = &'p Pixel>,
+    ) -> Self {
+        let histogram = colouring.needs_statistics().then(|| Histogram::new(pixels, settings.iters));
+        Colourizer {
+            colouring,
+            // Banded and smooth colours divide by log2 of the limit, which is 0 for a single iteration.
+            iters: settings.iters.max(2),
+            degree: settings.degree(),
+            histogram,
+        }
+    }
+
+    fn interior_colour(&self, p: &Pixel) -> [u8; 4] {
+        let t = match self.colouring.interior_mode {
+            InteriorMode::Flat => None,
+            // Periods are spread over the palette a sixteenth at a time, so neighbours differ.
+            InteriorMode::Period => (p.period > 0).then(|| ((p.period - 1) as f64 / 16.0).fract()),
+            InteriorMode::Magnitude => Some(p.z.abs() / 2.0),
+            InteriorMode::MinMagnitude => p.min_abs.is_finite().then(|| (p.min_abs / 2.0).sqrt()),
+            InteriorMode::Angle => Some((p.angle() + PI) / (2.0 * PI)),
+            InteriorMode::Trap => p.trap_distance.is_finite().then(|| trap_shade(p.trap_distance)),
+        };
+
+        let [r, g, b] = match t {
+            Some(t) => self.colouring.palette.sample(self.colouring.cycle(t)),
+            None => self.colouring.interior,
+        };
+        [r, g, b, 255]
+    }
+
+    pub fn colour(&self, p: &Pixel) -> [u8; 4] {
+        if !p.escaped {
+            return self.interior_colour(p);
+        }
+
+        let smooth = || p.smooth_escapes(self.degree).max(1.0).log2() / (self.iters as f64).log2();
+        let t = match self.colouring.mode {
+            ColourMode::Banded => p.escapes.max(1).ilog2() as f64 / self.iters.ilog2() as f64,
+            ColourMode::Smooth | ColourMode::Lighting => smooth(),
+            ColourMode::Histogram => match &self.histogram {
+                Some(histogram) => histogram.rank(p.smooth_escapes(self.degree)),
+                None => 0.0,
+            },
+            ColourMode::Distance => (p.distance / DISTANCE_FALLOFF).sqrt().min(1.0),
+            ColourMode::Trap => trap_shade(p.trap_distance),
+        };
+
+        let [r, g, b] = self.colouring.palette.sample(self.colouring.cycle(t));
+
+        if self.colouring.mode == ColourMode::Lighting {
+            let shade = p.normal().map_or(1.0, |n| self.colouring.light.shade(n));
+            let lit = |c: u8| (c as f64 * (AMBIENT + (1.0 - AMBIENT) * shade)).round() as u8;
+            return [lit(r), lit(g), lit(b), 255];
+        }
+
+        [r, g, b, 255]
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct Complex {
+    pub real: f64,
+    pub imag: f64,
+}
+
+impl Complex {
+    pub fn new(real: f64, imag: f64) -> Self {
+        Complex { real, imag }
+    }
+
+    pub fn square(&mut self) {
+        let real_part = self.real * self.real - self.imag * self.imag;
+        let imag_part = (self.real + self.real) * self.imag;
+
+        self.real = real_part;
+        self.imag = imag_part;
+    }
+
+    pub fn mag(&self) -> f64 {
+        self.imag * self.imag + self.real * self.real
+    }
+
+    pub fn abs(&self) -> f64 {
+        self.mag().sqrt()
+    }
+
+    pub fn conj(self) -> Complex {
+        Complex {
+            real: self.real,
+            imag: -self.imag,
+        }
+    }
+
+    pub fn scale(self, factor: f64) -> Complex {
+        Complex {
+            real: self.real * factor,
+            imag: self.imag * factor,
+        }
+    }
+
+    // z^n by repeated squaring.
+    pub fn powi(self, n: u32) -> Complex {
+        let mut result = Complex::new(1.0, 0.0);
+        let mut base = self;
+        let mut n = n;
+        while n > 0 {
+            if n & 1 == 1 {
+                result = result * base;
+            }
+            base.square();
+            n >>= 1;
+        }
+        result
+    }
+
+    // Principal value of z^w, exp(w ln z) in polar form. 0^w is taken to be 0.
+    pub fn powc(self, w: Complex) -> Complex {
+        if self.real == 0.0 && self.imag == 0.0 {
+            return Complex::default();
+        }
+        let ln_r = self.mag().ln() / 2.0;
+        let theta = self.imag.atan2(self.real);
+        let r = (w.real * ln_r - w.imag * theta).exp();
+        let angle = w.real * theta + w.imag * ln_r;
+        Complex::new(r * angle.cos(), r * angle.sin())
+    }
+
+    pub fn exp(self) -> Complex {
+        let r = self.real.exp();
+        Complex::new(r * self.imag.cos(), r * self.imag.sin())
+    }
+
+    // Principal branch, with the argument in (-pi, pi].
+    pub fn ln(self) -> Complex {
+        Complex::new(self.mag().ln() / 2.0, self.imag.atan2(self.real))
+    }
+
+    pub fn sqrt(self) -> Complex {
+        self.powc(Complex::new(0.5, 0.0))
+    }
+
+    pub fn sin(self) -> Complex {
+        Complex::new(self.real.sin() * self.imag.cosh(), self.real.cos() * self.imag.sinh())
+    }
+
+    pub fn cos(self) -> Complex {
+        Complex::new(self.real.cos() * self.imag.cosh(), -self.real.sin() * self.imag.sinh())
+    }
+
+    pub fn tan(self) -> Complex {
+        self.sin() / self.cos()
+    }
+
+    pub fn sinh(self) -> Complex {
+        Complex::new(self.real.sinh() * self.imag.cos(), self.real.cosh() * self.imag.sin())
+    }
+
+    pub fn cosh(self) -> Complex {
+        Complex::new(self.real.cosh() * self.imag.cos(), self.real.sinh() * self.imag.sin())
+    }
+
+    pub fn tanh(self) -> Complex {
+        self.sinh() / self.cosh()
+    }
+}
+
+// `re,im`, or just `re` when the imaginary part is zero.
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.imag == 0.0 {
+            write!(f, "{:?}", self.real)
+        } else {
+            write!(f, "{:?},{:?}", self.real, self.imag)
+        }
+    }
+}
+
+impl FromStr for Complex {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || format!("expected a number or <re>,<im>, got `{}`", s);
+        let (real, imag) = s.split_once(',').unwrap_or((s, "0"));
+        let real = real.trim().parse().map_err(|_| bad())?;
+        let imag = imag.trim().parse().map_err(|_| bad())?;
+        Ok(Complex::new(real, imag))
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex {
+            real: self.real + other.real,
+            imag: self.imag + other.imag,
+        }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex {
+            real: self.real - other.real,
+            imag: self.imag - other.imag,
+        }
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex {
+            real: -self.real,
+            imag: -self.imag,
+        }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            real: self.real * other.real - self.imag * other.imag,
+            imag: self.real * other.imag + self.imag * other.real,
+        }
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, other: Complex) -> Complex {
+        let denominator = other.mag();
+        Complex {
+            real: (self.real * other.real + self.imag * other.imag) / denominator,
+            imag: (self.imag * other.real - self.real * other.imag) / denominator,
+        }
+    }
+}
@@ -0,0 +1,508 @@
+use std::f64::consts::{E, PI};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::complex::Complex;
+use crate::formula::Power;
+
+// A user-written iteration step such as `z^3 - z + c` or `sin(z) * c`, compiled once into a tree
+// of closures so iterating it does not walk the syntax tree.
+//
+// Names: `z`, `c`, `pixel` (the point being rendered, which is c for the Mandelbrot family and
+// the starting z for a Julia set), and the constants `i`, `pi` and `e`. Numbers may carry an `i`
+// suffix (`0.5i`). Operators are `+ - * / ^` with the usual precedence, `^` binding tightest and
+// to the right. Functions take one argument: sin, cos, tan, sinh, cosh, tanh, exp, log (or ln),
+// sqrt, abs, conj, re and im.
+#[derive(Clone)]
+pub struct Expression {
+    source: String,
+    degree: Option<f64>,
+    eval: Arc<Compiled>,
+}
+
+type Compiled = dyn Fn(Complex, Complex, Complex) -> Complex + Send + Sync;
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, String> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+        };
+        let node = parser.expression()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("expected an operator"));
+        }
+
+        Ok(Expression {
+            source: source.trim().to_string(),
+            degree: node.degree(),
+            eval: Arc::from(compile(&node)),
+        })
+    }
+
+    pub fn eval(&self, z: Complex, c: Complex, pixel: Complex) -> Complex {
+        (self.eval)(z, c, pixel)
+    }
+
+    // How fast |z| grows per step far from the origin, as a power of |z|. Only known for
+    // polynomial-like expressions; None once z goes through a transcendental function.
+    pub fn degree(&self) -> Option<f64> {
+        self.degree
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+// Formulas that only differ in spacing are the same formula.
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        let compact = |source: &str| source.split_whitespace().collect::<String>();
+        compact(&self.source) == compact(&other.source)
+    }
+}
+
+impl fmt::Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Expression").field(&self.source).finish()
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Expression::parse(s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Var {
+    Z,
+    C,
+    Pixel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+impl Op {
+    fn apply(self, a: Complex, b: Complex) -> Complex {
+        match self {
+            Op::Add => a + b,
+            Op::Sub => a - b,
+            Op::Mul => a * b,
+            Op::Div => a / b,
+            // The polar form would make 0^0 zero.
+            Op::Pow if b == Complex::default() => Complex::new(1.0, 0.0),
+            Op::Pow => Power::of(b).apply(a),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Exp,
+    Log,
+    Sqrt,
+    Abs,
+    Conj,
+    Re,
+    Im,
+}
+
+impl Function {
+    fn named(name: &str) -> Option<Function> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "sinh" => Function::Sinh,
+            "cosh" => Function::Cosh,
+            "tanh" => Function::Tanh,
+            "exp" => Function::Exp,
+            "log" | "ln" => Function::Log,
+            "sqrt" => Function::Sqrt,
+            "abs" => Function::Abs,
+            "conj" => Function::Conj,
+            "re" => Function::Re,
+            "im" => Function::Im,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, z: Complex) -> Complex {
+        match self {
+            Function::Sin => z.sin(),
+            Function::Cos => z.cos(),
+            Function::Tan => z.tan(),
+            Function::Sinh => z.sinh(),
+            Function::Cosh => z.cosh(),
+            Function::Tanh => z.tanh(),
+            Function::Exp => z.exp(),
+            Function::Log => z.ln(),
+            Function::Sqrt => z.sqrt(),
+            Function::Abs => Complex::new(z.abs(), 0.0),
+            Function::Conj => z.conj(),
+            Function::Re => Complex::new(z.real, 0.0),
+            Function::Im => Complex::new(z.imag, 0.0),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Number(Complex),
+    Var(Var),
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+// The constructors fold anything that does not depend on a variable into a single number.
+impl Node {
+    fn neg(a: Node) -> Node {
+        match a {
+            Node::Number(x) => Node::Number(-x),
+            a => Node::Neg(Box::new(a)),
+        }
+    }
+
+    fn binary(op: Op, a: Node, b: Node) -> Node {
+        match (a, b) {
+            (Node::Number(x), Node::Number(y)) => Node::Number(op.apply(x, y)),
+            (_, Node::Number(w)) if op == Op::Pow && w == Complex::default() => Node::Number(Complex::new(1.0, 0.0)),
+            (a, b) => Node::Binary(op, Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn call(function: Function, a: Node) -> Node {
+        match a {
+            Node::Number(x) => Node::Number(function.apply(x)),
+            a => Node::Call(function, Box::new(a)),
+        }
+    }
+
+    fn degree(&self) -> Option<f64> {
+        match self {
+            Node::Number(_) | Node::Var(Var::C) | Node::Var(Var::Pixel) => Some(0.0),
+            Node::Var(Var::Z) => Some(1.0),
+            Node::Neg(a) => a.degree(),
+            Node::Binary(Op::Add | Op::Sub, a, b) => Some(a.degree()?.max(b.degree()?)),
+            Node::Binary(Op::Mul, a, b) => Some(a.degree()? + b.degree()?),
+            Node::Binary(Op::Div, a, b) => Some(a.degree()? - b.degree()?),
+            Node::Binary(Op::Pow, a, b) => match **b {
+                Node::Number(w) if w.imag == 0.0 => Some(a.degree()? * w.real),
+                _ => None,
+            },
+            Node::Call(Function::Abs | Function::Conj | Function::Re | Function::Im, a) => a.degree(),
+            Node::Call(_, a) => (a.degree()? == 0.0).then_some(0.0),
+        }
+    }
+}
+
+fn compile(node: &Node) -> Box<Compiled> {
+    match node {
+        &Node::Number(x) => Box::new(move |_, _, _| x),
+        Node::Var(Var::Z) => Box::new(|z, _, _| z),
+        Node::Var(Var::C) => Box::new(|_, c, _| c),
+        Node::Var(Var::Pixel) => Box::new(|_, _, pixel| pixel),
+        Node::Neg(a) => {
+            let a = compile(a);
+            Box::new(move |z, c, pixel| -a(z, c, pixel))
+        }
+        Node::Binary(op, a, b) => match (op, &**b) {
+            // Constant exponents pick their fastest form up front, the same way the Multibrot does.
+            (Op::Pow, &Node::Number(w)) => {
+                let power = Power::of(w);
+                let a = compile(a);
+                Box::new(move |z, c, pixel| power.apply(a(z, c, pixel)))
+            }
+            _ => {
+                let op = *op;
+                let (a, b) = (compile(a), compile(b));
+                Box::new(move |z, c, pixel| op.apply(a(z, c, pixel), b(z, c, pixel)))
+            }
+        },
+        Node::Call(function, a) => {
+            let function = *function;
+            let a = compile(a);
+            Box::new(move |z, c, pixel| function.apply(a(z, c, pixel)))
+        }
+    }
+}
+
+// Recursive descent over
+//   expression = term { ("+" | "-") term }
+//   term       = unary { ("*" | "/") unary }
+//   unary      = ("-" | "+") unary | power
+//   power      = atom [ "^" unary ]
+//   atom       = number | name | name "(" expression ")" | "(" expression ")"
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn error(&self, what: &str) -> String {
+        match self.chars.get(self.pos) {
+            Some(c) => format!("{}, found `{}` at column {}", what, c, self.pos + 1),
+            None => format!("{}, found the end of the formula", what),
+        }
+    }
+
+    fn expression(&mut self) -> Result<Node, String> {
+        let mut node = self.term()?;
+        loop {
+            if self.eat('+') {
+                node = Node::binary(Op::Add, node, self.term()?);
+            } else if self.eat('-') {
+                node = Node::binary(Op::Sub, node, self.term()?);
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        loop {
+            if self.eat('*') {
+                node = Node::binary(Op::Mul, node, self.unary()?);
+            } else if self.eat('/') {
+                node = Node::binary(Op::Div, node, self.unary()?);
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat('-') {
+            Ok(Node::neg(self.unary()?))
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(Node::binary(Op::Pow, base, self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let node = self.expression()?;
+                if !self.eat(')') {
+                    return Err(self.error("expected `)`"));
+                }
+                Ok(node)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() => self.name(),
+            _ => Err(self.error("expected a number, a name or `(`")),
+        }
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(|&c| pred(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<Node, String> {
+        let start = self.pos;
+        let mut text = self.take_while(|c| c.is_ascii_digit() || c == '.');
+
+        // A decimal exponent as in `1e-3`; the `e` only counts when digits follow.
+        let exponent_len = match self.chars[self.pos..] {
+            ['e' | 'E', '+' | '-', d, ..] if d.is_ascii_digit() => 2,
+            ['e' | 'E', d, ..] if d.is_ascii_digit() => 1,
+            _ => 0,
+        };
+        if exponent_len > 0 {
+            text.extend(&self.chars[self.pos..self.pos + exponent_len]);
+            self.pos += exponent_len;
+            text += &self.take_while(|c| c.is_ascii_digit());
+        }
+
+        let value: f64 = text.parse().map_err(|_| {
+            self.pos = start;
+            self.error("expected a number")
+        })?;
+
+        let imaginary = self.chars.get(self.pos) == Some(&'i')
+            && !self.chars.get(self.pos + 1).is_some_and(|c| c.is_alphanumeric() || *c == '_');
+        if imaginary {
+            self.pos += 1;
+            return Ok(Node::Number(Complex::new(0.0, value)));
+        }
+        Ok(Node::Number(Complex::new(value, 0.0)))
+    }
+
+    fn name(&mut self) -> Result<Node, String> {
+        let start = self.pos;
+        let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+
+        if self.eat('(') {
+            let Some(function) = Function::named(&name) else {
+                return Err(format!("unknown function `{}` at column {}", name, start + 1));
+            };
+            let argument = self.expression()?;
+            if !self.eat(')') {
+                return Err(self.error("expected `)`"));
+            }
+            return Ok(Node::call(function, argument));
+        }
+
+        Ok(match name.as_str() {
+            "z" => Node::Var(Var::Z),
+            "c" => Node::Var(Var::C),
+            "pixel" => Node::Var(Var::Pixel),
+            "i" => Node::Number(Complex::new(0.0, 1.0)),
+            "pi" => Node::Number(Complex::new(PI, 0.0)),
+            "e" => Node::Number(Complex::new(E, 0.0)),
+            _ => {
+                return Err(format!(
+                    "unknown name `{}` at column {} (expected z, c, pixel, i, pi or e)",
+                    name,
+                    start + 1
+                ))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, z: Complex, c: Complex) -> Complex {
+        Expression::parse(source).unwrap_or_else(|e| panic!("{}: {}", source, e)).eval(z, c, c)
+    }
+
+    fn assert_close(source: &str, z: Complex, c: Complex, expected: Complex) {
+        let value = eval(source, z, c);
+        assert!((value - expected).abs() < 1e-12, "{} gave {}, expected {}", source, value, expected);
+    }
+
+    fn real(x: f64) -> Complex {
+        Complex::new(x, 0.0)
+    }
+
+    fn error(source: &str) -> String {
+        Expression::parse(source).err().unwrap_or_else(|| panic!("{} should not parse", source))
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        let zero = Complex::default();
+        assert_close("-z^2", real(3.0), zero, real(-9.0));
+        assert_close("z^2^3", real(2.0), zero, real(256.0));
+        assert_close("2^-1", zero, zero, real(0.5));
+        assert_close("1 + 2 * 3 - 4 / 2", zero, zero, real(5.0));
+        assert_close("z - c - 1", real(10.0), real(3.0), real(6.0));
+        assert_close("2 * -z", real(3.0), zero, real(-6.0));
+        assert_close("(1 + 2) * 3", zero, zero, real(9.0));
+    }
+
+    #[test]
+    fn constants_and_suffixes() {
+        let zero = Complex::default();
+        assert_close("e", zero, zero, real(E));
+        assert_close("e*2", zero, zero, real(2.0 * E));
+        assert_close("1e-3", zero, zero, real(0.001));
+        assert_close("2E3", zero, zero, real(2000.0));
+        assert_close("pi", zero, zero, real(PI));
+        assert_close("2i", zero, zero, Complex::new(0.0, 2.0));
+        assert_close("2.5i * i", zero, zero, real(-2.5));
+        assert!(error("in").starts_with("unknown name `in` at column 1"));
+        assert_eq!(error("2in"), "expected an operator, found `i` at column 2");
+    }
+
+    #[test]
+    fn errors_point_at_the_column() {
+        assert_eq!(error("z + * c"), "expected a number, a name or `(`, found `*` at column 5");
+        assert_eq!(error("z c"), "expected an operator, found `c` at column 3");
+        assert_eq!(error("z +"), "expected a number, a name or `(`, found the end of the formula");
+        assert_eq!(error("(z + c"), "expected `)`, found the end of the formula");
+        assert_eq!(error("z + foo(z)"), "unknown function `foo` at column 5");
+        assert!(error("z + y").starts_with("unknown name `y` at column 5"));
+    }
+
+    #[test]
+    fn degree_of_polynomials_only() {
+        let degree = |source: &str| Expression::parse(source).unwrap().degree();
+        assert_eq!(degree("z^3 - z + c"), Some(3.0));
+        assert_eq!(degree("z^2 + c"), Some(2.0));
+        assert_eq!(degree("z^2 / z + sin(c)"), Some(1.0));
+        assert_eq!(degree("c"), Some(0.0));
+        assert_eq!(degree("sin(z) * c"), None);
+        assert_eq!(degree("z^c"), None);
+    }
+
+    #[test]
+    fn evaluates_to_hand_computed_values() {
+        let i = Complex::new(0.0, 1.0);
+        assert_close("z^2 + c", Complex::new(1.0, 2.0), Complex::new(0.5, -1.0), Complex::new(-2.5, 3.0));
+        assert_close("z^3 - z + c", i, real(1.0), Complex::new(1.0, -2.0));
+        assert_close("conj(z) * pixel", Complex::new(1.0, 1.0), Complex::new(2.0, 1.0), Complex::new(3.0, -1.0));
+        assert_close("abs(z) + re(c) + im(c)", Complex::new(3.0, 4.0), Complex::new(1.0, 2.0), real(8.0));
+        assert_close("exp(i * pi)", Complex::default(), Complex::default(), real(-1.0));
+        assert_close("z^0", Complex::default(), Complex::default(), real(1.0));
+        assert_close("z^0", Complex::new(2.0, 3.0), Complex::default(), real(1.0));
+        assert_close("z^c", Complex::default(), Complex::default(), real(1.0));
+    }
+
+    #[test]
+    fn equality_ignores_spacing() {
+        let parse = |source: &str| Expression::parse(source).unwrap();
+        assert_eq!(parse("z^2+c"), parse(" z^2 + c "));
+        assert_ne!(parse("z^2 + c"), parse("z^3 + c"));
+    }
+}
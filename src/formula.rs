@@ -0,0 +1,272 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::complex::Complex;
+use crate::expression::Expression;
+use crate::render::cardioid_or_bulb_period;
+
+// One iteration step of an escape-time fractal, z -> f(z, c).
+pub trait Formula {
+    fn step(&self, z: Complex, c: Complex) -> Complex;
+
+    // d step / dz, used for distance estimates and lighting. None for formulas that are not
+    // holomorphic in z, which then render without them.
+    fn derivative(&self, _z: Complex) -> Option<Complex> {
+        None
+    }
+
+    // Period of the attracting cycle when c is known to lie in the set without iterating.
+    fn interior_period(&self, _c: Complex) -> Option<i32> {
+        None
+    }
+
+    // Where the orbit of c starts outside Julia mode: the critical point 0 for the built-ins.
+    fn start(&self, _c: Complex) -> Complex {
+        Complex::default()
+    }
+}
+
+// How z is raised to the exponent: squared, multiplied out for other whole powers, or through
+// the polar form for real and complex ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Power {
+    Square,
+    Whole(u32),
+    Polar(Complex),
+}
+
+impl Power {
+    pub fn of(exponent: Complex) -> Power {
+        if exponent.imag != 0.0 || exponent.real.fract() != 0.0 || exponent.real < 1.0 {
+            Power::Polar(exponent)
+        } else if exponent.real == 2.0 {
+            Power::Square
+        } else {
+            Power::Whole(exponent.real as u32)
+        }
+    }
+
+    pub fn apply(self, z: Complex) -> Complex {
+        match self {
+            Power::Square => {
+                let mut z = z;
+                z.square();
+                z
+            }
+            Power::Whole(n) => z.powi(n),
+            Power::Polar(w) => z.powc(w),
+        }
+    }
+
+    // d/dz z^w = w z^(w-1)
+    pub fn derivative(self, z: Complex) -> Complex {
+        match self {
+            Power::Square => z.scale(2.0),
+            Power::Whole(n) => z.powi(n - 1).scale(n as f64),
+            Power::Polar(w) => z.powc(Complex::new(w.real - 1.0, w.imag)) * w,
+        }
+    }
+}
+
+// z^n + c; the Mandelbrot set for n = 2.
+pub struct Multibrot(pub Power);
+
+impl Formula for Multibrot {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        self.0.apply(z) + c
+    }
+
+    fn derivative(&self, z: Complex) -> Option<Complex> {
+        Some(self.0.derivative(z))
+    }
+
+    // The cardioid and bulb are shapes of the exponent 2 set only.
+    fn interior_period(&self, c: Complex) -> Option<i32> {
+        match self.0 {
+            Power::Square => cardioid_or_bulb_period(c),
+            _ => None,
+        }
+    }
+}
+
+// (|x| + i|y|)^n + c
+pub struct BurningShip(pub Power);
+
+impl Formula for BurningShip {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        self.0.apply(Complex::new(z.real.abs(), z.imag.abs())) + c
+    }
+}
+
+// conj(z)^n + c, also known as the Mandelbar set.
+pub struct Tricorn(pub Power);
+
+impl Formula for Tricorn {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        self.0.apply(z.conj()) + c
+    }
+}
+
+// |Re z^n| + i Im z^n + c
+pub struct Celtic(pub Power);
+
+impl Formula for Celtic {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        let z = self.0.apply(z);
+        Complex::new(z.real.abs(), z.imag) + c
+    }
+}
+
+// |Re z^n| + i |Im z^n| + c
+pub struct Buffalo(pub Power);
+
+impl Formula for Buffalo {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        let z = self.0.apply(z);
+        Complex::new(z.real.abs(), z.imag.abs()) + c
+    }
+}
+
+// (|x| - iy)^n + c, the Burning Ship with only the real part folded.
+pub struct Perpendicular(pub Power);
+
+impl Formula for Perpendicular {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        self.0.apply(Complex::new(z.real.abs(), -z.imag)) + c
+    }
+}
+
+// A formula typed in by the user: the iteration step, optionally preceded by where orbits start,
+// as in `pixel; sin(z) * c`. Without a start orbits begin at 0, which some formulas never leave.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserFormula {
+    pub start: Option<Expression>,
+    pub step: Expression,
+}
+
+impl fmt::Display for UserFormula {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.start {
+            Some(start) => write!(f, "{}; {}", start, self.step),
+            None => write!(f, "{}", self.step),
+        }
+    }
+}
+
+impl FromStr for UserFormula {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(';') {
+            Some((start, step)) => Ok(UserFormula {
+                start: Some(start.parse().map_err(|e| format!("start: {}", e))?),
+                step: step.parse().map_err(|e| format!("step: {}", e))?,
+            }),
+            None => Ok(UserFormula {
+                start: None,
+                step: s.parse()?,
+            }),
+        }
+    }
+}
+
+// A user formula, bound to the pixel it is iterated for.
+pub struct Custom<'a> {
+    pub formula: &'a UserFormula,
+    pub pixel: Complex,
+}
+
+impl Formula for Custom<'_> {
+    fn step(&self, z: Complex, c: Complex) -> Complex {
+        self.formula.step.eval(z, c, self.pixel)
+    }
+
+    fn start(&self, c: Complex) -> Complex {
+        let zero = Complex::default();
+        self.formula.start.as_ref().map_or(zero, |start| start.eval(zero, c, self.pixel))
+    }
+}
+
+// The built-in formulas, all iterated with `RenderSettings::exponent`, or a user formula.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Fractal {
+    #[default]
+    Mandelbrot,
+    BurningShip,
+    Tricorn,
+    Celtic,
+    Buffalo,
+    Perpendicular,
+    Custom(UserFormula),
+}
+
+impl Fractal {
+    // Cycles through the built-ins; a user formula is followed by the Mandelbrot set.
+    pub fn next(&self) -> Fractal {
+        match self {
+            Fractal::Mandelbrot => Fractal::BurningShip,
+            Fractal::BurningShip => Fractal::Tricorn,
+            Fractal::Tricorn => Fractal::Celtic,
+            Fractal::Celtic => Fractal::Buffalo,
+            Fractal::Buffalo => Fractal::Perpendicular,
+            Fractal::Perpendicular | Fractal::Custom(_) => Fractal::Mandelbrot,
+        }
+    }
+
+    // Whether orbits carry a derivative, which distance estimates and lighting need. Only the
+    // Multibrot family is holomorphic in z; user formulas are not differentiated.
+    pub fn has_derivative(&self) -> bool {
+        *self == Fractal::Mandelbrot
+    }
+
+    // Centre and width of a view showing the whole set for exponent 2. The imaginary axis points
+    // down the screen, which puts the Burning Ship the right way up.
+    pub fn default_view(&self) -> (Complex, f64) {
+        match self {
+            Fractal::Mandelbrot => (Complex::new(-0.5, 0.0), 5.0),
+            Fractal::BurningShip => (Complex::new(-0.4, -0.5), 4.0),
+            Fractal::Tricorn => (Complex::new(-0.4, 0.0), 4.5),
+            Fractal::Celtic => (Complex::new(-0.8, 0.0), 4.5),
+            Fractal::Buffalo => (Complex::new(-0.8, -0.4), 4.0),
+            Fractal::Perpendicular => (Complex::new(-0.6, 0.0), 4.5),
+            Fractal::Custom(_) => (Complex::default(), 5.0),
+        }
+    }
+}
+
+impl fmt::Display for Fractal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Fractal::Custom(formula) => return write!(f, "{}", formula),
+            Fractal::Mandelbrot => "mandelbrot",
+            Fractal::BurningShip => "burning-ship",
+            Fractal::Tricorn => "tricorn",
+            Fractal::Celtic => "celtic",
+            Fractal::Buffalo => "buffalo",
+            Fractal::Perpendicular => "perpendicular",
+        })
+    }
+}
+
+impl FromStr for Fractal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mandelbrot" => Ok(Fractal::Mandelbrot),
+            "burning-ship" => Ok(Fractal::BurningShip),
+            "tricorn" => Ok(Fractal::Tricorn),
+            "celtic" => Ok(Fractal::Celtic),
+            "buffalo" => Ok(Fractal::Buffalo),
+            "perpendicular" => Ok(Fractal::Perpendicular),
+            // Anything else is taken as a formula.
+            _ => s.parse().map(Fractal::Custom).map_err(|e| {
+                format!(
+                    "`{}` is neither a built-in fractal (mandelbrot, burning-ship, tricorn, celtic, buffalo, \
+                     perpendicular) nor a valid formula: {}",
+                    s, e
+                )
+            }),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::io;
+use std::path::Path;
+
+use crate::colour::{Colourizer, Colouring};
+use crate::png;
+use crate::render::{mandelbrod, Pixel, RenderSettings};
+use crate::screen::ScreenInfo;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    pub rgba: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: i32, height: i32) -> Self {
+        Image {
+            width,
+            height,
+            rgba: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: i32, h: i32, colour: [u8; 4]) {
+        for row in y.max(0)..(y + h).min(self.height) {
+            for col in x.max(0)..(x + w).min(self.width) {
+                let offset = (row as usize * self.width as usize + col as usize) * 4;
+                self.rgba[offset..offset + 4].copy_from_slice(&colour);
+            }
+        }
+    }
+
+    // Each sample is drawn as a `block` x `block` square starting at its own coordinates.
+    pub fn paint(&mut self, pixels: &[Pixel], block: i32, colourizer: &Colourizer) {
+        for p in pixels {
+            self.fill_rect(p.x, p.y, block, block, colourizer.colour(p));
+        }
+    }
+
+    pub fn from_pixels(
+        pixels: &[Pixel],
+        width: i32,
+        height: i32,
+        settings: &RenderSettings,
+        colouring: &Colouring,
+    ) -> Self {
+        let mut image = Image::new(width, height);
+        image.paint(pixels, settings.accuracy, &Colourizer::new(colouring, settings, pixels));
+        image
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        png::write_rgba(path, self.width as u32, self.height as u32, &self.rgba)
+    }
+}
+
+// Renders the view of `screen` at the requested output resolution without touching any window.
+pub fn render_image(
+    screen: ScreenInfo,
+    width: i32,
+    height: i32,
+    settings: &RenderSettings,
+    colouring: &Colouring,
+) -> Image {
+    let screen = screen.with_size(width, height);
+    let settings = &colouring.adjust(settings);
+    Image::from_pixels(&mandelbrod(screen, settings), width, height, settings, colouring)
+}
+
+pub fn export_png<P: AsRef<Path>>(
+    screen: ScreenInfo,
+    width: i32,
+    height: i32,
+    settings: &RenderSettings,
+    colouring: &Colouring,
+    path: P,
+) -> io::Result<()> {
+    render_image(screen, width, height, settings, colouring).save_png(path)
+}
@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+use crate::pool::ThreadPool;
+use crate::render::{passes, render_tile, tiles, Pass, Pixel, RenderSettings, Tile};
+use crate::screen::ScreenInfo;
+
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TileResult {
+    pub tile: Tile,
+    pub pass: Pass,
+    pub pixels: Vec<Pixel>,
+}
+
+// A render running on the pool in the background. Finished tiles are collected with `poll`;
+// dropping the job cancels whatever has not been computed yet.
+pub struct RenderJob {
+    pub screen: ScreenInfo,
+    pub settings: RenderSettings,
+    cancel: CancelToken,
+    results: Receiver<(usize, TileResult)>,
+    // Each tile's results are handed out pass by pass so a coarse block never paints over a finer
+    // one; tiles do not wait for each other. Work item `i` is tile `i % tiles` of pass `i / tiles`.
+    pending: Vec<Option<TileResult>>,
+    tiles: usize,
+    // Number of passes handed out so far for every tile.
+    released: Vec<usize>,
+    done: usize,
+}
+
+// Work is every tile of the coarsest pass, then every tile of the next one, and so on. Each
+// worker keeps pulling the next unclaimed item off a shared counter, so threads that land in
+// the cheap escape region simply take more tiles instead of sitting idle.
+pub fn start_render(pool: &ThreadPool, screen: ScreenInfo, settings: &RenderSettings) -> RenderJob {
+    let settings = settings.clone();
+    let passes = passes(settings.accuracy);
+    let tiles = tiles(&screen, passes[0].step);
+    let work: Arc<Vec<(Pass, Tile)>> = Arc::new(
+        passes
+            .iter()
+            .flat_map(|&pass| tiles.iter().map(move |&tile| (pass, tile)))
+            .collect(),
+    );
+    let next_item = Arc::new(AtomicUsize::new(0));
+    let cancel = CancelToken::default();
+
+    let (tx, rx) = mpsc::channel();
+
+    for _ in 0..pool.size().min(work.len()) {
+        let tx = tx.clone();
+        let work = Arc::clone(&work);
+        let next_item = Arc::clone(&next_item);
+        let cancel = cancel.clone();
+        let settings = settings.clone();
+        pool.execute(move || loop {
+            let i = next_item.fetch_add(1, Ordering::Relaxed);
+            let Some(&(pass, tile)) = work.get(i) else {
+                break;
+            };
+            let Some(pixels) = render_tile(&screen, &settings, &tile, pass, &cancel) else {
+                break;
+            };
+            if tx.send((i, TileResult { tile, pass, pixels })).is_err() {
+                break;
+            }
+        });
+    }
+
+    RenderJob {
+        screen,
+        settings,
+        cancel,
+        results: rx,
+        pending: vec![None; work.len()],
+        tiles: tiles.len(),
+        released: vec![0; tiles.len()],
+        done: 0,
+    }
+}
+
+impl RenderJob {
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done == self.pending.len()
+    }
+
+    // Fraction of the work handed out so far.
+    pub fn progress(&self) -> f32 {
+        if self.pending.is_empty() {
+            1.0
+        } else {
+            self.done as f32 / self.pending.len() as f32
+        }
+    }
+
+    // Stores a finished item and hands out whatever of its tile is now next in line.
+    fn receive(&mut self, i: usize, result: TileResult, released: &mut Vec<TileResult>) {
+        self.pending[i] = Some(result);
+        let tile = i % self.tiles;
+        loop {
+            let next = self.released[tile] * self.tiles + tile;
+            let Some(result) = self.pending.get_mut(next).and_then(Option::take) else {
+                break;
+            };
+            released.push(result);
+            self.released[tile] += 1;
+            self.done += 1;
+        }
+    }
+
+    // Tiles finished since the last call, without blocking.
+    pub fn poll(&mut self) -> Vec<TileResult> {
+        let mut released = Vec::new();
+        while let Ok((i, result)) = self.results.try_recv() {
+            self.receive(i, result, &mut released);
+        }
+        released
+    }
+
+    // Blocks until every remaining tile is done and returns them.
+    pub fn wait(&mut self) -> Vec<TileResult> {
+        let mut released = self.poll();
+        while !self.is_done() {
+            match self.results.recv() {
+                Ok((i, result)) => self.receive(i, result, &mut released),
+                Err(_) => break,
+            }
+        }
+        released
+    }
+}
+
+impl Drop for RenderJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::complex::Complex;
+
+    #[test]
+    fn tiles_refine_in_pass_order_and_cover_the_grid_once() {
+        // A step that does not divide the tile size, on a screen that cuts off the last tiles.
+        let accuracy = 3;
+        let screen = ScreenInfo::centered(Complex::new(-0.5, 0.0), 3.0, 150, 100);
+        let settings = RenderSettings {
+            iters: 50,
+            accuracy,
+            ..Default::default()
+        };
+        let pool = ThreadPool::new(3);
+
+        let mut job = start_render(&pool, screen, &settings);
+        let results = job.wait();
+        assert!(job.is_done());
+        assert_eq!(job.progress(), 1.0);
+
+        let mut steps: HashMap<(i32, i32), Vec<i32>> = HashMap::new();
+        let mut samples: HashMap<(i32, i32), usize> = HashMap::new();
+        for result in &results {
+            steps.entry((result.tile.x, result.tile.y)).or_default().push(result.pass.step);
+            for p in &result.pixels {
+                *samples.entry((p.x, p.y)).or_default() += 1;
+            }
+        }
+
+        let expected: Vec<i32> = passes(accuracy).iter().map(|pass| pass.step).collect();
+        assert_eq!(expected, [12, 6, 3]);
+        assert_eq!(steps.len(), tiles(&screen, expected[0]).len());
+        for (tile, steps) in &steps {
+            assert_eq!(steps, &expected, "passes of the tile at {:?}", tile);
+        }
+
+        for y in 0..screen.screen_height {
+            for x in 0..screen.screen_width {
+                let on_grid = x % accuracy == 0 && y % accuracy == 0;
+                let count = samples.get(&(x, y)).copied().unwrap_or(0);
+                assert_eq!(count, on_grid as usize, "samples of pixel ({}, {})", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+pub mod cli;
+pub mod colour;
+pub mod complex;
+pub mod expression;
+pub mod formula;
+pub mod image;
+pub mod job;
+pub mod location;
+pub mod palette;
+pub mod palette_file;
+pub mod png;
+pub mod pool;
+pub mod render;
+pub mod screen;
+pub mod trap;
+
+pub use colour::{ColourMode, Colourizer, Colouring, Histogram, InteriorMode, Light};
+pub use complex::Complex;
+pub use expression::Expression;
+pub use formula::{
+    Buffalo, BurningShip, Celtic, Custom, Formula, Fractal, Multibrot, Perpendicular, Power, Tricorn, UserFormula,
+};
+pub use image::{export_png, render_image, Image};
+pub use job::{start_render, CancelToken, RenderJob, TileResult};
+pub use location::Location;
+pub use palette::Palette;
+pub use pool::ThreadPool;
+pub use render::{
+    belongs_to_set, cardioid_or_bulb_period, in_cardioid_or_bulb, iterate, mandelbrod, mandelbrod_on, passes,
+    render_tile, tiles, Pass, Pixel, RenderCache, RenderSettings, Tile, ACCURACY, ITERS,
+};
+pub use screen::ScreenInfo;
+pub use trap::{Trap, TrapShape};
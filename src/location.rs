@@ -0,0 +1,199 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::colour::Colouring;
+use crate::complex::Complex;
+use crate::formula::Fractal;
+use crate::palette::{format_rgb, parse_rgb};
+use crate::render::RenderSettings;
+use crate::screen::ScreenInfo;
+
+// Everything needed to regenerate a saved image exactly, stored as `key = value` lines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Location {
+    pub x_start: f64,
+    pub x_stop: f64,
+    pub y_start: f64,
+    pub y_stop: f64,
+    pub width: i32,
+    pub height: i32,
+    pub iters: i32,
+    pub accuracy: i32,
+    pub periodicity: bool,
+    pub fractal: Fractal,
+    pub exponent: Complex,
+    pub julia: Option<Complex>,
+    pub colouring: Colouring,
+}
+
+impl Location {
+    pub fn of(screen: &ScreenInfo, settings: &RenderSettings, colouring: &Colouring) -> Self {
+        Location {
+            x_start: screen.x_start,
+            x_stop: screen.x_stop,
+            y_start: screen.y_start,
+            y_stop: screen.y_stop,
+            width: screen.screen_width,
+            height: screen.screen_height,
+            iters: settings.iters,
+            accuracy: settings.accuracy,
+            periodicity: settings.periodicity,
+            fractal: settings.fractal.clone(),
+            exponent: settings.exponent,
+            julia: settings.julia,
+            colouring: colouring.clone(),
+        }
+    }
+
+    pub fn screen(&self) -> ScreenInfo {
+        ScreenInfo::from((self.x_start, self.x_stop, self.y_start, self.y_stop, 1.0)).with_size(self.width, self.height)
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        RenderSettings {
+            iters: self.iters,
+            accuracy: self.accuracy,
+            periodicity: self.periodicity,
+            fractal: self.fractal.clone(),
+            exponent: self.exponent,
+            julia: self.julia,
+            ..Default::default()
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Location> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // {:?} on f64 prints the shortest representation that round-trips exactly.
+        writeln!(f, "x_start = {:?}", self.x_start)?;
+        writeln!(f, "x_stop = {:?}", self.x_stop)?;
+        writeln!(f, "y_start = {:?}", self.y_start)?;
+        writeln!(f, "y_stop = {:?}", self.y_stop)?;
+        writeln!(f, "width = {}", self.width)?;
+        writeln!(f, "height = {}", self.height)?;
+        writeln!(f, "iters = {}", self.iters)?;
+        writeln!(f, "accuracy = {}", self.accuracy)?;
+        writeln!(f, "periodicity = {}", self.periodicity)?;
+        writeln!(f, "fractal = {}", self.fractal)?;
+        writeln!(f, "exponent = {}", self.exponent)?;
+        if let Some(c) = self.julia {
+            writeln!(f, "julia = {}", c)?;
+        }
+        writeln!(f, "colouring = {}", self.colouring.mode)?;
+        writeln!(f, "palette = {}", self.colouring.palette)?;
+        writeln!(f, "palette_scale = {:?}", self.colouring.scale)?;
+        writeln!(f, "palette_offset = {:?}", self.colouring.offset)?;
+        writeln!(f, "interior = {}", format_rgb(self.colouring.interior))?;
+        writeln!(f, "interior_colouring = {}", self.colouring.interior_mode)?;
+        writeln!(f, "light_angle = {:?}", self.colouring.light.angle)?;
+        writeln!(f, "light_height = {:?}", self.colouring.light.height)?;
+        writeln!(f, "trap = {}", self.colouring.trap)
+    }
+}
+
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut location = Location {
+            x_start: 0.0,
+            x_stop: 0.0,
+            y_start: 0.0,
+            y_stop: 0.0,
+            width: 0,
+            height: 0,
+            iters: 0,
+            accuracy: 1,
+            periodicity: RenderSettings::default().periodicity,
+            fractal: Fractal::default(),
+            exponent: RenderSettings::default().exponent,
+            julia: None,
+            colouring: Colouring::default(),
+        };
+
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("expected `key = value`, got `{}`", line))?;
+            let (key, value) = (key.trim(), value.trim());
+            let bad = || format!("invalid value for {}: `{}`", key, value);
+
+            match key {
+                "x_start" => location.x_start = value.parse().map_err(|_| bad())?,
+                "x_stop" => location.x_stop = value.parse().map_err(|_| bad())?,
+                "y_start" => location.y_start = value.parse().map_err(|_| bad())?,
+                "y_stop" => location.y_stop = value.parse().map_err(|_| bad())?,
+                "width" => location.width = value.parse().map_err(|_| bad())?,
+                "height" => location.height = value.parse().map_err(|_| bad())?,
+                "iters" => location.iters = value.parse().map_err(|_| bad())?,
+                "accuracy" => location.accuracy = value.parse().map_err(|_| bad())?,
+                "periodicity" => location.periodicity = value.parse().map_err(|_| bad())?,
+                "fractal" => location.fractal = value.parse()?,
+                "exponent" => location.exponent = value.parse()?,
+                "julia" => location.julia = Some(value.parse()?),
+                "colouring" => location.colouring.mode = value.parse()?,
+                "palette" => location.colouring.palette = value.parse()?,
+                "palette_scale" => location.colouring.scale = value.parse().map_err(|_| bad())?,
+                "palette_offset" => location.colouring.offset = value.parse().map_err(|_| bad())?,
+                "interior" => location.colouring.interior = parse_rgb(value)?,
+                "interior_colouring" => location.colouring.interior_mode = value.parse()?,
+                "light_angle" => location.colouring.light.angle = value.parse().map_err(|_| bad())?,
+                "light_height" => location.colouring.light.height = value.parse().map_err(|_| bad())?,
+                "trap" => location.colouring.trap = value.parse()?,
+                _ => return Err(format!("unknown key `{}`", key)),
+            }
+        }
+
+        Ok(location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::{ColourMode, InteriorMode};
+
+    #[test]
+    fn round_trips_through_text() {
+        let screen = ScreenInfo::centered(Complex::new(-0.743643887037151, 0.131825904205330), 1.7e-6, 640, 480);
+        let settings = RenderSettings {
+            iters: 2500,
+            accuracy: 3,
+            periodicity: false,
+            fractal: "pixel; sin(z) * c + 0.1i".parse().unwrap(),
+            exponent: Complex::new(2.5, -0.25),
+            julia: Some(Complex::new(-0.8, 0.156)),
+            ..Default::default()
+        };
+        let colouring = Colouring {
+            mode: ColourMode::Trap,
+            palette: "0:#000000,0.3:#ff8800,1:#ffffff".parse().unwrap(),
+            scale: 2.5,
+            offset: 0.1,
+            interior: [16, 32, 48],
+            interior_mode: InteriorMode::Angle,
+            trap: "cross:0.25,-0.5,30".parse().unwrap(),
+            ..Default::default()
+        };
+
+        let location = Location::of(&screen, &settings, &colouring);
+        let parsed: Location = location.to_string().parse().unwrap();
+        assert_eq!(parsed, location);
+        assert_eq!(parsed.screen(), location.screen());
+        assert_eq!(parsed.settings(), settings);
+        assert_eq!(parsed.colouring, colouring);
+    }
+}
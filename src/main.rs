@@ -1,195 +1,278 @@
+use mandelbrod::cli::{self, Options};
+use mandelbrod::{Colouring, Fractal, Location, RenderCache, RenderSettings, ScreenInfo, ThreadPool};
 use raylib::prelude::*;
-use std::sync::mpsc;
-use std::thread;
-use std::ops::Add;
-
-#[derive(Clone, Copy, Default)]
-struct Complex {
-    real: f64,
-    imag: f64,
-}
-
-#[derive(Copy, Clone, Default)]
-struct Pixel {
-    x: i32,
-    y: i32,
-    escapes: i32,
-}
-
-#[derive(Clone, Copy)]
-struct ScreenInfo {
-    x_start: f64,
-    x_stop: f64,
-    y_start: f64,
-    y_stop: f64,
-    pixels_per_cm: f64,
-    screen_width: i32,
-    screen_height: i32,
-}
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The Julia preview takes this fraction of the window in each direction.
+const PREVIEW_SHARE: i32 = 4;
+// The preview has to keep up with the mouse, so it stops iterating early.
+const PREVIEW_ITERS: i32 = 500;
+const PREVIEW_MARGIN: i32 = 8;
+const PREVIEW_THREADS: usize = 2;
+// Interior colours the O key cycles through, along with the one given on the command line.
+const INTERIOR_COLOURS: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [128, 128, 128], [0, 7, 100]];
 
-impl ScreenInfo {
-    fn zoom(&mut self, how_many_times: f64, mouse_pos: Vector2) {
-        let view_width = self.x_stop - self.x_start;
-        let view_height = self.y_stop - self.y_start;
-
-        let mouse_world_x = self.x_start + mouse_pos.x as f64 / self.screen_width as f64 * view_width;
-        let mouse_world_y = self.y_start + mouse_pos.y as f64 / self.screen_height as f64 * view_height;
-
-        let new_width = view_width / how_many_times;
-        let new_height = view_height / how_many_times;
-
-        self.x_start = mouse_world_x - new_width / 2.0;
-        self.x_stop = mouse_world_x + new_width / 2.0;
-
-        self.y_start = mouse_world_y - new_height / 2.0;
-        self.y_stop = mouse_world_y + new_height / 2.0;
-    }
-}
-
-impl From<(f64, f64, f64, f64, f64)> for ScreenInfo {
-    fn from(values: (f64, f64, f64, f64, f64)) -> Self {
-        let (x_start, x_stop, y_start, y_stop, pixels_per_cm) = values;
-
-        ScreenInfo {
-            x_start,
-            x_stop,
-            y_start,
-            y_stop,
-            pixels_per_cm,
-
-            screen_width: (pixels_per_cm * (x_stop - x_start)) as i32,
-            screen_height: (pixels_per_cm * (y_stop - y_start)) as i32,
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
         }
-    }
-}
-
-const MAX_THREADS: i32 = 64;
-const ACCURACY: i32 = 2;
-const ITERS: i32 = 10000;
-
-impl Complex {
-    fn square(&mut self) {
-        let real_part = self.real * self.real - self.imag * self.imag;
-        let imag_part = (self.real + self.real) * self.imag;
+    };
 
-        self.real = real_part;
-        self.imag = imag_part;
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
     }
 
-    fn mag(&self) -> f64 {
-        self.imag * self.imag + self.real * self.real
-    }
-}
-
-impl Add for Complex {
-    type Output = Complex;
-    fn add(self, other: Complex) -> Complex {
-        Complex {
-            real: self.real + other.real,
-            imag: self.imag + other.imag,
+    if options.headless {
+        let path = options.output_or_default();
+        let screen = options.screen;
+        let mut cache = RenderCache::new(Arc::new(ThreadPool::new(options.threads)));
+        cache.update(screen, &options.settings, &options.colouring);
+        cache.finish();
+        if let Err(e) = cache.image().save_png(&path) {
+            eprintln!("could not write {}: {}", path.display(), e);
+            process::exit(1);
         }
+        let location = Location::of(&screen, &options.settings, &options.colouring);
+        if let Err(e) = location.save(path.with_extension("txt")) {
+            eprintln!("could not write location file: {}", e);
+        }
+        return;
     }
+
+    run_viewer(options);
 }
 
-fn main() {
-    let mut screen = ScreenInfo::from((-3.0, 2.0, -2.0, 2.0, 200.0));
+fn run_viewer(options: Options) {
+    let mut screen = options.screen;
+    let mut settings = options.settings;
+    let mut colouring = options.colouring.clone();
+    // The Mandelbrot view to return to when leaving Julia mode.
+    let mut mandelbrot_screen = None;
 
     let (mut rl_handle, thread) = init()
         .size(screen.screen_width,screen.screen_height)
         .build();
+    rl_handle.set_target_fps(60);
+
+    let blank = Image::gen_image_color(screen.screen_width, screen.screen_height, Color::BLACK);
+    let mut texture = rl_handle
+        .load_texture_from_image(&thread, &blank)
+        .expect("could not create the frame texture");
+
+    let mut cache = RenderCache::new(Arc::new(ThreadPool::new(options.threads)));
+
+    // Julia set for the point under the cursor, shown in a corner while exploring the Mandelbrot set.
+    let preview_width = (screen.screen_width / PREVIEW_SHARE).max(1);
+    let preview_height = (screen.screen_height / PREVIEW_SHARE).max(1);
+    let preview_x = screen.screen_width - preview_width - PREVIEW_MARGIN;
+    let preview_y = screen.screen_height - preview_height - PREVIEW_MARGIN;
+    let preview_blank = Image::gen_image_color(preview_width, preview_height, Color::BLACK);
+    let mut preview_texture = rl_handle
+        .load_texture_from_image(&thread, &preview_blank)
+        .expect("could not create the preview texture");
+    // A few workers of its own, since on the view's pool it would wait for the whole main render.
+    let mut preview = RenderCache::new(Arc::new(ThreadPool::new(PREVIEW_THREADS)));
+    let mut show_preview = true;
 
     while !rl_handle.window_should_close() {
         let mouse_wheel_move = rl_handle.get_mouse_wheel_move();
+        let mouse_pos = rl_handle.get_mouse_position();
+        let mouse_world = screen.to_world(mouse_pos.x as f64, mouse_pos.y as f64);
 
         if mouse_wheel_move != 0.0 {
-            screen.zoom(if mouse_wheel_move > 0.0 { 1.25 } else {0.75}, rl_handle.get_mouse_position());
+            screen.zoom(if mouse_wheel_move > 0.0 { 1.25 } else {0.75}, mouse_pos.x as f64, mouse_pos.y as f64);
         }
 
-        let mut draw_handle = rl_handle.begin_drawing(&thread);
-        draw_handle.clear_background(Color::BLACK);
+        handle_colour_keys(&rl_handle, &mut colouring, &options.colouring);
+        if rl_handle.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_RIGHT) {
+            colouring.trap.center = mouse_world;
+        }
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_V) {
+            show_preview = !show_preview;
+        }
 
-        let mandelbrod = mandelbrod(screen);
-        draw_pixel_mandelbrod(&mandelbrod[..], &mut draw_handle);
+        // J or a left click opens the Julia set for the point under the cursor; J goes back.
+        let open_julia = rl_handle.is_key_pressed(KeyboardKey::KEY_J)
+            || rl_handle.is_mouse_button_pressed(MouseButton::MOUSE_BUTTON_LEFT);
+        if settings.julia.is_none() && open_julia {
+            settings.julia = Some(mouse_world);
+            mandelbrot_screen = Some(screen);
+            screen = default_screen(&settings, screen.screen_width, screen.screen_height);
+        } else if settings.julia.is_some() && rl_handle.is_key_pressed(KeyboardKey::KEY_J) {
+            settings.julia = None;
+            if let Some(saved) = mandelbrot_screen.take() {
+                screen = saved;
+            }
+        }
 
-        let fps = draw_handle.get_fps();
-        draw_handle.draw_text(format!("fps: {}", fps).as_str(), 3, 3, 10, Color::WHEAT);
+        // = and - raise and lower the exponent by one, or by a tenth with shift held.
+        let exponent_step = if rl_handle.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) { 0.1 } else { 1.0 };
+        let exponent = settings.exponent.real;
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_EQUAL) {
+            settings.exponent.real = ((exponent + exponent_step) * 10.0).round() / 10.0;
+        }
+        let lowered = ((exponent - exponent_step) * 10.0).round() / 10.0;
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_MINUS) && lowered >= cli::MIN_EXPONENT {
+            settings.exponent.real = lowered;
+        }
 
-    }
-}
+        // F switches to the next built-in formula and shows all of it.
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_F) {
+            settings.fractal = settings.fractal.next();
+            settings.julia = None;
+            mandelbrot_screen = None;
+            screen = default_screen(&settings, screen.screen_width, screen.screen_height);
+        }
 
-fn draw_pixel_mandelbrod(p: &[Pixel], draw_handle: &mut RaylibDrawHandle) {
-    p.iter().for_each(|p| {
-        let alpha: f32 = if p.escapes < 1 {
-            0.0
+        // Distance and lighting fall back to smooth colouring for formulas without a derivative.
+        let shown = colouring.supported(&settings.fractal);
+
+        let preview_visible = show_preview && settings.julia.is_none();
+        if preview_visible {
+            let preview_settings = RenderSettings {
+                iters: settings.iters.min(PREVIEW_ITERS),
+                julia: Some(mouse_world),
+                ..settings.clone()
+            };
+            let preview_screen = default_screen(&preview_settings, preview_width, preview_height);
+            if preview.update(preview_screen, &preview_settings, &shown) {
+                preview_texture
+                    .update_texture(&preview.image().rgba)
+                    .expect("preview size does not match the texture");
+            }
         } else {
-            p.escapes.ilog2() as f32 / ITERS.ilog2() as f32
-            //p.escapes as f32 / ITERS as f32
-        };
-
-        let color_shade = (alpha * 255.0) as u8;
-
-        draw_handle.draw_rectangle(
-            p.x,
-            p.y,
-            ACCURACY,
-            ACCURACY,
-            Color::new(color_shade, color_shade, color_shade, 255),
-        );
-    });
-}
-fn belongs_to_set(c: Complex, p: &mut Pixel) {
-    let mut z: Complex = Default::default();
-    for i in 0..ITERS {
-        if z.mag() > 16.0 {
-            p.escapes = i;
-            return;
+            preview.stop();
         }
-        z.square();
-        z = z + c;
-    }
-    p.escapes = 0;
-}
 
+        let mut frame_changed = cache.update(screen, &settings, &shown);
 
-fn mandelbrod(screen: ScreenInfo) -> Vec<Pixel> {
-    let mut threads: Vec<thread::JoinHandle<()>> = Vec::with_capacity(MAX_THREADS as usize + 1);
-    let rows_per_thread = (screen.screen_height as f32 / MAX_THREADS as f32).ceil() as i32;
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_S) {
+            frame_changed |= cache.finish();
+            match save_screenshot(&cache, &screen, &settings, &shown, options.output.clone()) {
+                Ok(path) => println!("saved {}", path.display()),
+                Err(e) => eprintln!("could not save screenshot: {}", e),
+            }
+        }
 
-    let (tx, rx) = mpsc::channel();
+        if frame_changed {
+            texture
+                .update_texture(&cache.image().rgba)
+                .expect("frame size does not match the texture");
+        }
 
-    for i in 0..=MAX_THREADS {
-        let tx = tx.clone();
-        threads.push(thread::spawn(move || {
-            let mut temp_data = Vec::with_capacity((rows_per_thread * screen.screen_width) as usize);
-            let start_y = i * rows_per_thread;
-            let end_y = (i + 1) * rows_per_thread;
+        let mut draw_handle = rl_handle.begin_drawing(&thread);
+        draw_handle.clear_background(Color::BLACK);
+        draw_handle.draw_texture(&texture, 0, 0, Color::WHITE);
 
-            for y in (start_y..end_y).step_by(ACCURACY as usize) {
-                for x in (0..screen.screen_width).step_by(ACCURACY as usize) {
-                    let c = Complex {
-                        real: screen.x_start + x as f64 / screen.screen_width as f64 * (screen.x_stop - screen.x_start),
-                        imag: screen.y_start + y as f64 / screen.screen_height as f64 * (screen.y_stop - screen.y_start),
-                    };
+        let mut hud = vec![format!("fps: {}", draw_handle.get_fps())];
+        if !cache.is_complete() {
+            hud.push(format!("rendering {}%", (cache.progress() * 100.0) as i32));
+        }
+        if settings.fractal != Fractal::Mandelbrot {
+            hud.push(settings.fractal.to_string());
+        }
+        if settings.exponent != RenderSettings::default().exponent {
+            hud.push(format!("exponent {}", settings.exponent));
+        }
+        if shown.mode != colouring.mode {
+            hud.push(format!("{} needs a derivative, showing {}", colouring.mode, shown.mode));
+        }
+        if let Some(c) = settings.julia {
+            hud.push(format!("julia c = {:.6} {:+.6}i", c.real, c.imag));
+        }
+        for (i, line) in hud.iter().enumerate() {
+            draw_handle.draw_text(line.as_str(), 3, 3 + 12 * i as i32, 10, Color::WHEAT);
+        }
 
-                    let mut p = Pixel { x, y, escapes: 0 };
+        if preview_visible {
+            draw_handle.draw_texture(&preview_texture, preview_x, preview_y, Color::WHITE);
+            let (x, y) = (preview_x - 1, preview_y - 1);
+            draw_handle.draw_rectangle_lines(x, y, preview_width + 2, preview_height + 2, Color::WHEAT);
+        }
 
-                    belongs_to_set(c, &mut p);
-                    temp_data.push(p);
-                }
-            }
-            tx.send(temp_data).unwrap();
-        }))
     }
+}
 
-    let mut canvas: Vec<Pixel> = Vec::with_capacity((screen.screen_width * screen.screen_height) as usize);
-    drop(tx);
-    for rec in rx {
-        canvas.extend(rec);
-    }
+// Shows the whole set `settings` renders in a window of the given size.
+fn default_screen(settings: &RenderSettings, width: i32, height: i32) -> ScreenInfo {
+    let (center, view_width) = settings.default_view();
+    ScreenInfo::centered(center, view_width, width, height)
+}
 
-    for thread in threads {
-        thread.join().unwrap();
+// P cycles palettes, M the colouring mode, I the interior colouring, O the interior colour,
+// T the orbit trap shape, [ and ] change how often the palette repeats, , and . shift it. The
+// arrow keys move the light: left/right turn it, up/down raise and lower it. The palette and
+// interior colour from `startup` stay in their cycles.
+fn handle_colour_keys(rl_handle: &RaylibHandle, colouring: &mut Colouring, startup: &Colouring) {
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_P) {
+        colouring.palette = colouring.palette.next_with(&startup.palette);
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_O) {
+        let mut choices = INTERIOR_COLOURS.to_vec();
+        if !choices.contains(&startup.interior) {
+            choices.push(startup.interior);
+        }
+        let i = choices.iter().position(|&c| c == colouring.interior).map_or(0, |i| i + 1);
+        colouring.interior = choices[i % choices.len()];
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_M) {
+        colouring.mode = colouring.mode.next();
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_I) {
+        colouring.interior_mode = colouring.interior_mode.next();
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_T) {
+        colouring.trap = colouring.trap.next_shape();
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+        colouring.scale *= 1.25;
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) {
+        colouring.scale /= 1.25;
+    }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_PERIOD) {
+        colouring.offset = (colouring.offset + 0.05).rem_euclid(1.0);
     }
+    if rl_handle.is_key_pressed(KeyboardKey::KEY_COMMA) {
+        colouring.offset = (colouring.offset - 0.05).rem_euclid(1.0);
+    }
+    if rl_handle.is_key_down(KeyboardKey::KEY_LEFT) {
+        colouring.light.angle = (colouring.light.angle - 3.0).rem_euclid(360.0);
+    }
+    if rl_handle.is_key_down(KeyboardKey::KEY_RIGHT) {
+        colouring.light.angle = (colouring.light.angle + 3.0).rem_euclid(360.0);
+    }
+    if rl_handle.is_key_down(KeyboardKey::KEY_UP) {
+        colouring.light.height += 0.05;
+    }
+    if rl_handle.is_key_down(KeyboardKey::KEY_DOWN) {
+        colouring.light.height = (colouring.light.height - 0.05).max(0.0);
+    }
+}
+
+// Writes the current frame as a png plus a `.txt` sidecar describing how to regenerate it.
+fn save_screenshot(
+    cache: &RenderCache,
+    screen: &ScreenInfo,
+    settings: &RenderSettings,
+    colouring: &Colouring,
+    output: Option<PathBuf>,
+) -> io::Result<PathBuf> {
+    let path = output.unwrap_or_else(|| {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        PathBuf::from(format!("mandelbrod-{}.png", secs))
+    });
+
+    cache.image().save_png(&path)?;
+    Location::of(screen, settings, colouring).save(path.with_extension("txt"))?;
 
-    canvas
+    Ok(path)
 }
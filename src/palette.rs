@@ -0,0 +1,212 @@
+use std::fmt;
+use std::str::FromStr;
+
+pub type Rgb = [u8; 3];
+
+// A gradient of colour stops over [0, 1]. Stops are kept sorted by position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub stops: Vec<(f64, Rgb)>,
+}
+
+const BUILTINS: &[(&str, &[(f64, u32)])] = &[
+    ("grey", &[(0.0, 0x000000), (1.0, 0xffffff)]),
+    (
+        "ultra",
+        &[
+            (0.0, 0x000764),
+            (0.16, 0x206bcb),
+            (0.42, 0xedffff),
+            (0.6425, 0xffaa00),
+            (0.8575, 0x000200),
+            (1.0, 0x000764),
+        ],
+    ),
+    ("fire", &[(0.0, 0x000000), (0.3, 0x8b0000), (0.55, 0xff4500), (0.8, 0xffd700), (1.0, 0xffffff)]),
+    ("ocean", &[(0.0, 0x00040f), (0.35, 0x00407f), (0.7, 0x36c2e0), (1.0, 0xf0ffff)]),
+    (
+        "rainbow",
+        &[
+            (0.0, 0xff0000),
+            (0.17, 0xffff00),
+            (0.33, 0x00ff00),
+            (0.5, 0x00ffff),
+            (0.67, 0x0000ff),
+            (0.83, 0xff00ff),
+            (1.0, 0xff0000),
+        ],
+    ),
+    ("twilight", &[(0.0, 0xe2d9e2), (0.25, 0x5e43a5), (0.5, 0x2f1436), (0.75, 0xb2574e), (1.0, 0xe2d9e2)]),
+];
+
+pub fn builtin_names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(name, _)| *name)
+}
+
+fn hex_rgb(hex: u32) -> Rgb {
+    [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8]
+}
+
+pub fn parse_rgb(s: &str) -> Result<Rgb, String> {
+    let digits = s.trim().trim_start_matches('#');
+    if digits.len() != 6 {
+        return Err(format!("expected a #rrggbb colour, got `{}`", s));
+    }
+    u32::from_str_radix(digits, 16)
+        .map(hex_rgb)
+        .map_err(|_| format!("expected a #rrggbb colour, got `{}`", s))
+}
+
+pub fn format_rgb(rgb: Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+impl Palette {
+    pub fn new(name: &str, mut stops: Vec<(f64, Rgb)>) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err("a palette needs at least one colour".to_string());
+        }
+        if stops.iter().any(|(t, _)| !(0.0..=1.0).contains(t)) {
+            return Err("palette stop positions must lie between 0 and 1".to_string());
+        }
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Ok(Palette {
+            name: name.to_string(),
+            stops,
+        })
+    }
+
+    // Evenly spaced stops.
+    pub fn from_colours(name: &str, colours: &[Rgb]) -> Result<Self, String> {
+        let last = colours.len().saturating_sub(1).max(1) as f64;
+        let stops = colours.iter().enumerate().map(|(i, &c)| (i as f64 / last, c)).collect();
+        Palette::new(name, stops)
+    }
+
+    pub fn builtin(name: &str) -> Option<Palette> {
+        BUILTINS.iter().find(|(n, _)| *n == name).map(|(name, stops)| Palette {
+            name: name.to_string(),
+            stops: stops.iter().map(|&(t, hex)| (t, hex_rgb(hex))).collect(),
+        })
+    }
+
+    // The built-in palette following this one, wrapping around.
+    pub fn next_builtin(&self) -> Palette {
+        let i = BUILTINS.iter().position(|(n, _)| *n == self.name).map_or(0, |i| i + 1);
+        let (name, _) = BUILTINS[i % BUILTINS.len()];
+        Palette::builtin(name).unwrap()
+    }
+
+    // Like `next_builtin`, but a custom `own` palette, such as one loaded from a file, joins the
+    // cycle after the last built-in so it can be found again.
+    pub fn next_with(&self, own: &Palette) -> Palette {
+        let (last, _) = BUILTINS[BUILTINS.len() - 1];
+        if self.name == last && self.is_builtin() && !own.is_builtin() {
+            own.clone()
+        } else {
+            self.next_builtin()
+        }
+    }
+
+    fn is_builtin(&self) -> bool {
+        Palette::builtin(&self.name).as_ref() == Some(self)
+    }
+
+    pub fn sample(&self, t: f64) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+        let upper = self.stops.iter().position(|(pos, _)| *pos >= t);
+
+        let (lo, hi) = match upper {
+            None => return self.stops[self.stops.len() - 1].1,
+            Some(0) => return self.stops[0].1,
+            Some(i) => (self.stops[i - 1], self.stops[i]),
+        };
+
+        let span = hi.0 - lo.0;
+        let f = if span > 0.0 { (t - lo.0) / span } else { 1.0 };
+        let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * f).round() as u8;
+        [mix(lo.1[0], hi.1[0]), mix(lo.1[1], hi.1[1]), mix(lo.1[2], hi.1[2])]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::builtin("grey").unwrap()
+    }
+}
+
+// Built-ins print as their name, anything else as its stop list.
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_builtin() {
+            return f.write_str(&self.name);
+        }
+        let stops: Vec<String> = self
+            .stops
+            .iter()
+            .map(|&(t, rgb)| format!("{:?}:{}", t, format_rgb(rgb)))
+            .collect();
+        f.write_str(&stops.join(","))
+    }
+}
+
+// Either a built-in name, a list of evenly spaced colours (`#000000,#ff8800,#ffffff`) or a list
+// of explicit stops (`0:#000000,0.3:#ff8800,1:#ffffff`).
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(palette) = Palette::builtin(s) {
+            return Ok(palette);
+        }
+        if !s.contains('#') {
+            let names: Vec<&str> = builtin_names().collect();
+            return Err(format!("unknown palette `{}` (built-ins: {})", s, names.join(", ")));
+        }
+
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        if parts.iter().all(|p| p.contains(':')) {
+            let stops = parts
+                .iter()
+                .map(|p| {
+                    let (t, colour) = p.split_once(':').unwrap();
+                    let t = t.trim().parse().map_err(|_| format!("invalid stop position `{}`", t))?;
+                    Ok((t, parse_rgb(colour)?))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Palette::new("custom", stops)
+        } else {
+            let colours = parts.iter().map(|p| parse_rgb(p)).collect::<Result<Vec<_>, String>>()?;
+            Palette::from_colours("custom", &colours)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_palette_joins_the_builtin_cycle() {
+        let cycle = |own: &Palette| {
+            let mut palette = own.next_with(own);
+            let mut names = vec![palette.to_string()];
+            while palette != *own {
+                palette = palette.next_with(own);
+                names.push(palette.to_string());
+            }
+            names
+        };
+
+        let own: Palette = "#000000,#ff8800".parse().unwrap();
+        let mut expected: Vec<String> = builtin_names().map(String::from).collect();
+        expected.push(own.to_string());
+        assert_eq!(cycle(&own), expected);
+
+        let fire = Palette::builtin("fire").unwrap();
+        assert_eq!(cycle(&fire), ["ocean", "rainbow", "twilight", "grey", "ultra", "fire"]);
+    }
+}
@@ -0,0 +1,233 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::palette::{Palette, Rgb};
+
+// Segments of a GIMP gradient are resampled at this many points to turn them into linear stops.
+const GGR_SAMPLES_PER_SEGMENT: usize = 16;
+
+pub fn is_palette_file(path: &str) -> bool {
+    matches!(extension(Path::new(path)).as_deref(), Some("ggr" | "gpl" | "map"))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase())
+}
+
+// Loads a GIMP gradient (.ggr), GIMP palette (.gpl) or Fractint colour map (.map).
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Palette> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let name = path.file_stem().map_or("custom".into(), |s| s.to_string_lossy().into_owned());
+
+    let parsed = match extension(path).as_deref() {
+        Some("ggr") => parse_ggr(&text),
+        Some("gpl") => parse_gpl(&text, &name),
+        Some("map") => parse_map(&text, &name),
+        _ => Err("palette files must end in .ggr, .gpl or .map".to_string()),
+    };
+    parsed.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn channel(value: f64) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+fn parse_numbers(line: &str) -> Result<Vec<f64>, String> {
+    line.split_whitespace()
+        .map(|v| v.parse().map_err(|_| format!("invalid number `{}` in `{}`", v, line)))
+        .collect()
+}
+
+// Blending factor at `pos` within a segment whose midpoint sits at `mid`, both relative to the segment.
+fn ggr_blend(kind: i32, pos: f64, mid: f64) -> f64 {
+    let linear = if pos <= mid {
+        if mid > 0.0 { 0.5 * pos / mid } else { 0.0 }
+    } else if mid < 1.0 {
+        0.5 + 0.5 * (pos - mid) / (1.0 - mid)
+    } else {
+        1.0
+    };
+
+    match kind {
+        1 => pos.max(0.0).powf(0.5f64.ln() / mid.max(1e-10).ln()),
+        2 => ((-PI / 2.0 + PI * linear).sin() + 1.0) / 2.0,
+        3 => (1.0 - (linear - 1.0) * (linear - 1.0)).sqrt(),
+        4 => 1.0 - (1.0 - linear * linear).sqrt(),
+        5 => {
+            if pos >= mid {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        _ => linear,
+    }
+}
+
+// Colour-model flags (HSV segments) and alpha are ignored; colours are mixed in RGB.
+pub fn parse_ggr(text: &str) -> Result<Palette, String> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+
+    if lines.next() != Some("GIMP Gradient") {
+        return Err("missing `GIMP Gradient` header".to_string());
+    }
+
+    let mut name = "custom".to_string();
+    let mut line = lines.next().ok_or("missing segment count")?;
+    if let Some(n) = line.strip_prefix("Name:") {
+        name = n.trim().to_string();
+        line = lines.next().ok_or("missing segment count")?;
+    }
+    let count: usize = line.parse().map_err(|_| format!("invalid segment count `{}`", line))?;
+
+    let mut stops = Vec::new();
+    for _ in 0..count {
+        let line = lines.next().ok_or("fewer segments than announced")?;
+        let v = parse_numbers(line)?;
+        if v.len() < 11 {
+            return Err(format!("segment needs at least 11 values: `{}`", line));
+        }
+
+        let (left, mid, right) = (v[0], v[1], v[2]);
+        let kind = v.get(11).copied().unwrap_or(0.0) as i32;
+        let width = right - left;
+        let mid = if width > 0.0 { (mid - left) / width } else { 0.5 };
+
+        for i in 0..=GGR_SAMPLES_PER_SEGMENT {
+            let pos = i as f64 / GGR_SAMPLES_PER_SEGMENT as f64;
+            let f = ggr_blend(kind, pos, mid);
+            let mix = |a: f64, b: f64| channel(a + (b - a) * f);
+            stops.push((left + pos * width, [mix(v[3], v[7]), mix(v[4], v[8]), mix(v[5], v[9])]));
+        }
+    }
+
+    Palette::new(&name, stops)
+}
+
+pub fn parse_gpl(text: &str, default_name: &str) -> Result<Palette, String> {
+    let mut lines = text.lines().map(str::trim);
+
+    if lines.next() != Some("GIMP Palette") {
+        return Err("missing `GIMP Palette` header".to_string());
+    }
+
+    let mut name = default_name.to_string();
+    let mut colours = Vec::new();
+    for line in lines {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        if let Some(n) = line.strip_prefix("Name:") {
+            name = n.trim().to_string();
+            continue;
+        }
+        colours.push(parse_rgb_triple(line)?);
+    }
+
+    Palette::from_colours(&name, &colours)
+}
+
+// One `r g b` triple per line, usually 256 of them; anything after the third number is a comment.
+pub fn parse_map(text: &str, name: &str) -> Result<Palette, String> {
+    let colours = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with(';'))
+        .map(parse_rgb_triple)
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Palette::from_colours(name, &colours)
+}
+
+fn parse_rgb_triple(line: &str) -> Result<Rgb, String> {
+    let mut values = line.split_whitespace().take(3).map(|v| v.parse::<u8>());
+    match (values.next(), values.next(), values.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Ok([r, g, b]),
+        _ => Err(format!("expected `r g b` with values 0-255, got `{}`", line)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_blend(kind: i32, pos: f64, mid: f64, expected: f64) {
+        let f = ggr_blend(kind, pos, mid);
+        assert!((f - expected).abs() < 1e-9, "blend {} at {} (mid {}) gave {}", kind, pos, mid, f);
+    }
+
+    #[test]
+    fn ggr_blend_types() {
+        // Linear bends at the midpoint.
+        assert_blend(0, 0.2, 0.4, 0.25);
+        assert_blend(0, 0.7, 0.4, 0.75);
+        // Curved is 1/2 at the midpoint too, but smoothly.
+        assert_blend(1, 0.25, 0.25, 0.5);
+        assert_blend(1, 0.0, 0.25, 0.0);
+        assert_blend(1, 1.0, 0.25, 1.0);
+        assert_blend(2, 0.25, 0.5, (1.0 - 0.5f64.sqrt()) / 2.0);
+        assert_blend(2, 0.5, 0.5, 0.5);
+        assert_blend(2, 1.0, 0.5, 1.0);
+        // Sphere increasing and decreasing.
+        assert_blend(3, 0.5, 0.5, 0.75f64.sqrt());
+        assert_blend(4, 0.5, 0.5, 1.0 - 0.75f64.sqrt());
+        // Step.
+        assert_blend(5, 0.3, 0.4, 0.0);
+        assert_blend(5, 0.4, 0.4, 1.0);
+    }
+
+    const GGR: &str = "GIMP Gradient
+Name: Test
+2
+0.000000 0.250000 0.500000 0 0 0 1 1 1 1 1 0 0
+0.500000 0.750000 1.000000 1 1 1 1 1 0 0 1 2 0
+";
+
+    #[test]
+    fn ggr_segments_become_stops() {
+        let palette = parse_ggr(GGR).unwrap();
+        assert_eq!(palette.name, "Test");
+        assert_eq!(palette.stops.len(), 2 * (GGR_SAMPLES_PER_SEGMENT + 1));
+        assert_eq!(palette.sample(0.0), [0, 0, 0]);
+        assert_eq!(palette.sample(0.25), [128, 128, 128]);
+        // The second segment is sine blended from white to red.
+        assert_eq!(palette.sample(0.75), [255, 128, 128]);
+        assert_eq!(palette.sample(1.0), [255, 0, 0]);
+    }
+
+    #[test]
+    fn ggr_header_and_name_are_checked() {
+        let unnamed = GGR.replacen("Name: Test\n", "", 1);
+        assert_eq!(parse_ggr(&unnamed).unwrap().name, "custom");
+        assert_eq!(parse_ggr(&GGR[6..]).unwrap_err(), "missing `GIMP Gradient` header");
+        assert_eq!(parse_ggr(&GGR.replacen("\n2\n", "\n3\n", 1)).unwrap_err(), "fewer segments than announced");
+    }
+
+    #[test]
+    fn gpl_colours_are_spaced_evenly() {
+        let text = "GIMP Palette\nName: Two\nColumns: 4\n#\n  0   0   0\tBlack\n255 128   0\tOrange\n";
+        let palette = parse_gpl(text, "file").unwrap();
+        assert_eq!(palette.name, "Two");
+        assert_eq!(palette.stops, [(0.0, [0, 0, 0]), (1.0, [255, 128, 0])]);
+
+        let palette = parse_gpl("GIMP Palette\n10 20 30\n", "file").unwrap();
+        assert_eq!(palette.name, "file");
+        assert_eq!(palette.sample(0.7), [10, 20, 30]);
+
+        assert_eq!(parse_gpl("0 0 0\n", "file").unwrap_err(), "missing `GIMP Palette` header");
+    }
+
+    #[test]
+    fn map_ignores_comments() {
+        let text = "; made by hand\n0 0 0 black\n128 64 32 ; brown\n\n255 255 255\n";
+        let palette = parse_map(text, "file").unwrap();
+        assert_eq!(palette.name, "file");
+        assert_eq!(palette.stops, [(0.0, [0, 0, 0]), (0.5, [128, 64, 32]), (1.0, [255, 255, 255])]);
+
+        assert!(parse_map("0 0 0\n1 2\n", "file").is_err());
+        assert!(parse_map("0 0 256\n", "file").is_err());
+    }
+}
@@ -0,0 +1,569 @@
+use std::sync::Arc;
+
+use crate::colour::{Colourizer, Colouring};
+use crate::complex::Complex;
+use crate::formula::{
+    Buffalo, BurningShip, Celtic, Custom, Formula, Fractal, Multibrot, Perpendicular, Power, Tricorn,
+};
+use crate::image::Image;
+use crate::job::{start_render, CancelToken, RenderJob, TileResult};
+use crate::pool::ThreadPool;
+use crate::screen::ScreenInfo;
+use crate::trap::Trap;
+
+pub const ACCURACY: i32 = 1;
+pub const ITERS: i32 = 10000;
+// Cap on the escape radius: 2^(1/(d-1)) overflows as the degree d approaches 1.
+pub const MAX_ESCAPE_RADIUS: f64 = 1e10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub iters: i32,
+    pub accuracy: i32,
+    // Track dz/dc along the orbit, needed for distance estimates.
+    pub derivative: bool,
+    // Stop interior orbits as soon as they are caught in a cycle.
+    pub periodicity: bool,
+    // Track the smallest |z| and iterate points in the cardioid and period-2 bulb too, so every
+    // interior point gets orbit statistics.
+    pub orbit_stats: bool,
+    // Record the closest approach of every orbit to this trap.
+    pub trap: Option<Trap>,
+    pub fractal: Fractal,
+    // Power z is raised to each step; for the Mandelbrot formula 2 gives the Mandelbrot set,
+    // anything else a Multibrot.
+    pub exponent: Complex,
+    // Render the Julia set for this c instead of the Mandelbrot set.
+    pub julia: Option<Complex>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            iters: ITERS,
+            accuracy: ACCURACY,
+            derivative: false,
+            periodicity: true,
+            orbit_stats: false,
+            trap: None,
+            fractal: Fractal::default(),
+            exponent: Complex::new(2.0, 0.0),
+            julia: None,
+        }
+    }
+}
+
+impl RenderSettings {
+    // Centre and width of a view showing the whole set. Julia sets and the other exponents sit
+    // around the origin.
+    pub fn default_view(&self) -> (Complex, f64) {
+        let (center, width) = self.fractal.default_view();
+        if self.julia.is_some() {
+            (Complex::default(), 4.0)
+        } else if self.exponent != Complex::new(2.0, 0.0) {
+            (Complex::default(), width)
+        } else {
+            (center, width)
+        }
+    }
+
+    // Growth rate of |z| per iteration far from the origin.
+    pub fn degree(&self) -> f64 {
+        match &self.fractal {
+            Fractal::Custom(formula) => formula.step.degree().filter(|&d| d > 1.0).unwrap_or(2.0),
+            _ => self.exponent.real,
+        }
+    }
+
+    // Squared escape radius. Once |z| passes both |c| and 2^(1/(d-1)) the orbit can only grow,
+    // so this never lets an orbit go early; the floor of 4 keeps the smooth colouring accurate.
+    // Degrees too close to 1 hit `MAX_ESCAPE_RADIUS` instead.
+    pub fn bailout(&self) -> f64 {
+        let radius = 2f64.powf(1.0 / (self.degree() - 1.0)).clamp(4.0, MAX_ESCAPE_RADIUS);
+        let c = self.julia.map_or(0.0, |c| c.abs());
+        radius.max(c).powi(2)
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq)]
+pub struct Pixel {
+    pub x: i32,
+    pub y: i32,
+    // Whether the orbit crossed the bailout, after `escapes` iterations. A Julia orbit can start
+    // outside it and escape after 0.
+    pub escaped: bool,
+    pub escapes: i32,
+    // Value of z when the orbit escaped, or where an interior orbit ended up.
+    pub z: Complex,
+    // dz/dc when the orbit escaped, only tracked with `RenderSettings::derivative`.
+    pub dz: Complex,
+    // Estimated distance to the set in screen pixels; infinite when not tracked or inside the set.
+    pub distance: f64,
+    // Length of the cycle an interior orbit settled into, 0 when none was detected.
+    pub period: i32,
+    // Smallest |z| along the orbit (after the starting point), only tracked with
+    // `RenderSettings::orbit_stats`.
+    pub min_abs: f64,
+    // Closest approach of the orbit to `RenderSettings::trap`, infinite without a trap.
+    pub trap_distance: f64,
+}
+
+impl Pixel {
+    // Normalized iteration count: `escapes` plus the fraction of an iteration the orbit still had
+    // left before crossing the bailout, which is continuous across band edges. 0 for interior points.
+    // `degree` is `RenderSettings::degree` of the render that produced the pixel.
+    pub fn smooth_escapes(&self, degree: f64) -> f64 {
+        if !self.escaped {
+            return 0.0;
+        }
+        let log_z = self.z.mag().ln() / 2.0;
+        self.escapes as f64 + 1.0 - log_z.ln() / degree.ln()
+    }
+
+    // Exterior distance estimate |z| ln|z| / |dz| in units of the complex plane.
+    pub fn distance_estimate(&self) -> f64 {
+        let dz = self.dz.abs();
+        if !self.escaped || dz == 0.0 {
+            return f64::INFINITY;
+        }
+        let z = self.z.abs();
+        z * z.ln() / dz
+    }
+
+    // Argument of the final z, in (-pi, pi].
+    pub fn angle(&self) -> f64 {
+        self.z.imag.atan2(self.z.real)
+    }
+
+    // Direction of z / dz, the 2D surface normal of the potential field around the set.
+    pub fn normal(&self) -> Option<Complex> {
+        let u = self.z * self.dz.conj();
+        let len = u.abs();
+        (self.escaped && len > 0.0).then(|| u.scale(1.0 / len))
+    }
+}
+
+// Two orbit points closer than this in both coordinates count as the same point of a cycle.
+pub const PERIOD_EPSILON: f64 = 1e-13;
+
+// Points in the main cardioid or the period-2 bulb never escape, so they can skip the loop.
+// Returns the period of the attracting cycle: 1 in the cardioid, 2 in the bulb.
+pub fn cardioid_or_bulb_period(c: Complex) -> Option<i32> {
+    let x = c.real - 0.25;
+    let y2 = c.imag * c.imag;
+    let q = x * x + y2;
+    if q * (q + x) <= 0.25 * y2 {
+        return Some(1);
+    }
+
+    let x = c.real + 1.0;
+    (x * x + y2 <= 0.0625).then_some(2)
+}
+
+pub fn in_cardioid_or_bulb(c: Complex) -> bool {
+    cardioid_or_bulb_period(c).is_some()
+}
+
+// `point` is the pixel's position in the plane: c for the Mandelbrot set, z0 in Julia mode.
+// Iterates the formula selected by `settings.fractal`.
+pub fn belongs_to_set(point: Complex, p: &mut Pixel, settings: &RenderSettings) {
+    let power = Power::of(settings.exponent);
+    match &settings.fractal {
+        Fractal::Mandelbrot => iterate(&Multibrot(power), point, p, settings),
+        Fractal::BurningShip => iterate(&BurningShip(power), point, p, settings),
+        Fractal::Tricorn => iterate(&Tricorn(power), point, p, settings),
+        Fractal::Celtic => iterate(&Celtic(power), point, p, settings),
+        Fractal::Buffalo => iterate(&Buffalo(power), point, p, settings),
+        Fractal::Perpendicular => iterate(&Perpendicular(power), point, p, settings),
+        Fractal::Custom(formula) => iterate(&Custom { formula, pixel: point }, point, p, settings),
+    }
+}
+
+pub fn iterate<F: Formula + ?Sized>(formula: &F, point: Complex, p: &mut Pixel, settings: &RenderSettings) {
+    // Start from a clean pixel so nothing is left over when one is reused.
+    *p = Pixel {
+        x: p.x,
+        y: p.y,
+        distance: f64::INFINITY,
+        min_abs: f64::INFINITY,
+        trap_distance: f64::INFINITY,
+        ..Default::default()
+    };
+    if settings.julia.is_none() && !settings.orbit_stats {
+        if let Some(period) = formula.interior_period(point) {
+            p.period = period;
+            return;
+        }
+    }
+
+    // dz/dc picks up +1 every step for the Mandelbrot set; dz/dz0 starts at 1 for a Julia set.
+    let zero = Complex::default();
+    let one = Complex::new(1.0, 0.0);
+    let (mut z, c, mut dz, dz_step) = match settings.julia {
+        Some(c) => (point, c, one, zero),
+        None => (formula.start(point), point, zero, one),
+    };
+    let derivative = settings.derivative && formula.derivative(z).is_some();
+
+    // Brent's cycle detection: compare against a saved point that is replaced whenever the
+    // distance to it reaches the next power of two, which finds a cycle of any length.
+    let mut saved = z;
+    let mut power = 1;
+    let mut steps = 0;
+    let bailout = settings.bailout();
+
+    for i in 0..settings.iters {
+        if z.mag() > bailout {
+            p.escaped = true;
+            p.escapes = i;
+            p.z = z;
+            if derivative {
+                p.dz = dz;
+            }
+            return;
+        }
+        if derivative {
+            dz = formula.derivative(z).unwrap_or_default() * dz + dz_step;
+        }
+        z = formula.step(z, c);
+        if settings.orbit_stats {
+            p.min_abs = p.min_abs.min(z.abs());
+        }
+        if let Some(trap) = &settings.trap {
+            p.trap_distance = p.trap_distance.min(trap.distance(z));
+        }
+
+        if settings.periodicity {
+            steps += 1;
+            if (z.real - saved.real).abs() < PERIOD_EPSILON && (z.imag - saved.imag).abs() < PERIOD_EPSILON {
+                p.period = steps;
+                p.z = z;
+                return;
+            }
+            if steps == power {
+                saved = z;
+                power *= 2;
+                steps = 0;
+            }
+        }
+    }
+    p.z = z;
+}
+
+pub fn mandelbrod(screen: ScreenInfo, settings: &RenderSettings) -> Vec<Pixel> {
+    mandelbrod_on(ThreadPool::global(), screen, settings)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+pub const TILE_SIZE: i32 = 64;
+pub const COARSEST_STEP: i32 = 16;
+
+// One refinement pass. Unless `reuse` is false, the samples lying on the grid of the previous
+// pass (every `2 * step` pixels) are already known and get skipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pass {
+    pub step: i32,
+    pub reuse: bool,
+}
+
+// Sample steps from a fast blocky preview down to `accuracy`, halving every pass.
+pub fn passes(accuracy: i32) -> Vec<Pass> {
+    let mut step = accuracy;
+    let mut steps = vec![step];
+    while step * 2 <= COARSEST_STEP {
+        step *= 2;
+        steps.push(step);
+    }
+
+    steps
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &step)| Pass { step, reuse: i > 0 })
+        .collect()
+}
+
+// Splits the screen into tiles whose origins sit on the `step` sample grid.
+pub fn tiles(screen: &ScreenInfo, step: i32) -> Vec<Tile> {
+    let size = (TILE_SIZE / step).max(1) * step;
+    let mut tiles = Vec::new();
+
+    for y in (0..screen.screen_height).step_by(size as usize) {
+        for x in (0..screen.screen_width).step_by(size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: size.min(screen.screen_width - x),
+                height: size.min(screen.screen_height - y),
+            });
+        }
+    }
+    tiles
+}
+
+// Returns None as soon as `cancel` fires; the flag is checked once per row.
+pub fn render_tile(
+    screen: &ScreenInfo,
+    settings: &RenderSettings,
+    tile: &Tile,
+    pass: Pass,
+    cancel: &CancelToken,
+) -> Option<Vec<Pixel>> {
+    let step = pass.step;
+    let pixel_size = screen.pixel_size();
+    let mut temp_data = Vec::with_capacity((tile.width * tile.height / (step * step)) as usize + 1);
+
+    for y in (tile.y..tile.y + tile.height).step_by(step as usize) {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        for x in (tile.x..tile.x + tile.width).step_by(step as usize) {
+            if pass.reuse && x % (2 * step) == 0 && y % (2 * step) == 0 {
+                continue;
+            }
+
+            let c = screen.to_world(x as f64, y as f64);
+
+            let mut p = Pixel { x, y, ..Default::default() };
+
+            belongs_to_set(c, &mut p, settings);
+            if settings.derivative {
+                p.distance = p.distance_estimate() / pixel_size;
+            }
+            temp_data.push(p);
+        }
+    }
+    Some(temp_data)
+}
+
+pub fn mandelbrod_on(pool: &ThreadPool, screen: ScreenInfo, settings: &RenderSettings) -> Vec<Pixel> {
+    let mut canvas: Vec<Pixel> = Vec::with_capacity(screen.screen_width as usize * screen.screen_height as usize);
+    for result in start_render(pool, screen, settings).wait() {
+        canvas.extend(result.pixels);
+    }
+    canvas
+}
+
+// Keeps the last result around and only renders again when the view or settings change.
+// Renders run in the background; `update` paints whatever tiles finished since the last call.
+// Caches can share a pool, but a render keeps all of its workers until it is done, so a cache
+// that has to stay responsive next to another one needs a pool of its own.
+#[derive(Default)]
+pub struct RenderCache {
+    pool: Arc<ThreadPool>,
+    job: Option<RenderJob>,
+    results: Vec<TileResult>,
+    colouring: Colouring,
+    image: Image,
+}
+
+impl RenderCache {
+    pub fn new(pool: Arc<ThreadPool>) -> Self {
+        RenderCache {
+            pool,
+            job: None,
+            results: Vec::new(),
+            colouring: Colouring::default(),
+            image: Image::default(),
+        }
+    }
+
+    pub fn is_stale(&self, screen: &ScreenInfo, settings: &RenderSettings) -> bool {
+        match &self.job {
+            Some(job) => job.screen != *screen || job.settings != *settings,
+            None => true,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.job.as_ref().is_some_and(RenderJob::is_done)
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.job.as_ref().map_or(0.0, RenderJob::progress)
+    }
+
+    // Starts a new render if needed (abandoning any stale one) and paints finished tiles.
+    // A different `colouring` only repaints what was already computed. Returns true when
+    // `image()` changed.
+    pub fn update(&mut self, screen: ScreenInfo, settings: &RenderSettings, colouring: &Colouring) -> bool {
+        let settings = &colouring.adjust(settings);
+        let mut changed = false;
+
+        if self.is_stale(&screen, settings) {
+            self.job = Some(start_render(&self.pool, screen, settings));
+            self.results.clear();
+            if self.image.width != screen.screen_width || self.image.height != screen.screen_height {
+                self.image = Image::new(screen.screen_width, screen.screen_height);
+            }
+        }
+
+        if self.colouring != *colouring {
+            self.colouring = colouring.clone();
+            self.repaint();
+            changed = true;
+        }
+
+        let job = self.job.as_mut().unwrap();
+        let finished = job.poll();
+        self.paint(finished) || changed
+    }
+
+    // Abandons the current render, freeing its workers; the next `update` starts a new one.
+    pub fn stop(&mut self) {
+        self.job = None;
+    }
+
+    // Blocks until the current render is complete. Returns true when `image()` changed.
+    pub fn finish(&mut self) -> bool {
+        match self.job.as_mut() {
+            Some(job) => {
+                let finished = job.wait();
+                self.paint(finished)
+            }
+            None => false,
+        }
+    }
+
+    fn paint(&mut self, finished: Vec<TileResult>) -> bool {
+        let Some(job) = &self.job else {
+            return false;
+        };
+        if finished.is_empty() {
+            return false;
+        }
+
+        // Image-wide statistics shift with every new tile, so everything gets repainted.
+        if self.colouring.needs_statistics() {
+            self.results.extend(finished);
+            self.repaint();
+            return true;
+        }
+
+        let colourizer = Colourizer::new(&self.colouring, &job.settings, std::iter::empty());
+        for result in finished {
+            self.image.paint(&result.pixels, result.pass.step, &colourizer);
+            self.results.push(result);
+        }
+        true
+    }
+
+    fn repaint(&mut self) {
+        let Some(job) = &self.job else {
+            return;
+        };
+        let pixels = self.results.iter().flat_map(|result| result.pixels.iter());
+        let colourizer = Colourizer::new(&self.colouring, &job.settings, pixels);
+        for result in &self.results {
+            self.image.paint(&result.pixels, result.pass.step, &colourizer);
+        }
+    }
+
+    pub fn pixels(&self) -> impl Iterator<Item = &Pixel> {
+        self.results.iter().flat_map(|result| result.pixels.iter())
+    }
+
+    pub fn colouring(&self) -> &Colouring {
+        &self.colouring
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The plain escape loop without any interior shortcuts.
+    fn plain_escapes(c: Complex, iters: i32) -> i32 {
+        let mut z = Complex::default();
+        for i in 0..iters {
+            if z.mag() > 16.0 {
+                return i;
+            }
+            z.square();
+            z = z + c;
+        }
+        0
+    }
+
+    #[test]
+    fn periodicity_finds_known_periods() {
+        let settings = RenderSettings::default();
+        // Centres of the period-3 and period-4 components, not covered by the cardioid/bulb test.
+        for (c, period) in [(Complex::new(-0.122561, 0.744862), 3), (Complex::new(0.282271, 0.530061), 4)] {
+            let mut p = Pixel::default();
+            belongs_to_set(c, &mut p, &settings);
+            assert_eq!((p.escaped, p.escapes, p.period), (false, 0, period));
+        }
+    }
+
+    #[test]
+    fn julia_points_outside_the_bailout_escape_at_once() {
+        let settings = RenderSettings {
+            julia: Some(Complex::new(-0.8, 0.156)),
+            ..Default::default()
+        };
+        let mut p = Pixel::default();
+        belongs_to_set(Complex::new(5.0, 0.0), &mut p, &settings);
+        assert!(p.escaped);
+        assert_eq!(p.escapes, 0);
+    }
+
+    #[test]
+    fn reused_pixels_keep_nothing_from_the_last_orbit() {
+        let settings = RenderSettings {
+            derivative: true,
+            ..Default::default()
+        };
+        let fresh = |point: Complex| {
+            let mut p = Pixel::default();
+            belongs_to_set(point, &mut p, &settings);
+            p
+        };
+
+        let mut p = Pixel::default();
+        // Escaping, in the cardioid, periodic outside the cardioid and escaping again.
+        for point in [Complex::new(0.3, 0.6), Complex::new(0.0, 0.0), Complex::new(-1.3, 0.0), Complex::new(1.0, 1.0)] {
+            belongs_to_set(point, &mut p, &settings);
+            assert_eq!(p, fresh(point), "{}", point);
+        }
+    }
+
+    #[test]
+    fn bailout_stays_finite_for_degrees_near_one() {
+        let settings = RenderSettings {
+            exponent: Complex::new(1.0000001, 0.0),
+            ..Default::default()
+        };
+        assert_eq!(settings.bailout(), MAX_ESCAPE_RADIUS.powi(2));
+
+        let mut p = Pixel::default();
+        belongs_to_set(Complex::new(1e6, 0.0), &mut p, &settings);
+        assert!(p.escaped);
+    }
+
+    #[test]
+    fn interior_checks_do_not_change_the_image() {
+        let screen = ScreenInfo::from((-3.0, 2.0, -2.0, 2.0, 40.0));
+        let settings = RenderSettings::default();
+
+        let pixels = mandelbrod(screen, &settings);
+        assert_eq!(pixels.len(), 200 * 160);
+
+        for p in &pixels {
+            let c = screen.to_world(p.x as f64, p.y as f64);
+            assert_eq!(p.escapes, plain_escapes(c, settings.iters), "pixel ({}, {})", p.x, p.y);
+        }
+        assert!(pixels.iter().any(|p| in_cardioid_or_bulb(screen.to_world(p.x as f64, p.y as f64))));
+    }
+}
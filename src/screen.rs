@@ -0,0 +1,87 @@
+use crate::complex::Complex;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenInfo {
+    pub x_start: f64,
+    pub x_stop: f64,
+    pub y_start: f64,
+    pub y_stop: f64,
+    pub pixels_per_cm: f64,
+    pub screen_width: i32,
+    pub screen_height: i32,
+}
+
+impl ScreenInfo {
+    // A view `view_width` wide around `center`, with the height following the pixel aspect ratio.
+    pub fn centered(center: Complex, view_width: f64, width: i32, height: i32) -> ScreenInfo {
+        let view_height = view_width * height as f64 / width as f64;
+        ScreenInfo::from((
+            center.real - view_width / 2.0,
+            center.real + view_width / 2.0,
+            center.imag - view_height / 2.0,
+            center.imag + view_height / 2.0,
+            1.0,
+        ))
+        .with_size(width, height)
+    }
+
+    pub fn center(&self) -> Complex {
+        Complex::new((self.x_start + self.x_stop) / 2.0, (self.y_start + self.y_stop) / 2.0)
+    }
+
+    pub fn zoom(&mut self, how_many_times: f64, mouse_x: f64, mouse_y: f64) {
+        let view_width = self.x_stop - self.x_start;
+        let view_height = self.y_stop - self.y_start;
+
+        let mouse_world = self.to_world(mouse_x, mouse_y);
+
+        let new_width = view_width / how_many_times;
+        let new_height = view_height / how_many_times;
+
+        self.x_start = mouse_world.real - new_width / 2.0;
+        self.x_stop = mouse_world.real + new_width / 2.0;
+
+        self.y_start = mouse_world.imag - new_height / 2.0;
+        self.y_stop = mouse_world.imag + new_height / 2.0;
+    }
+
+    // Same view of the plane, sampled at a different output resolution.
+    pub fn with_size(&self, width: i32, height: i32) -> ScreenInfo {
+        ScreenInfo {
+            pixels_per_cm: width as f64 / (self.x_stop - self.x_start),
+            screen_width: width,
+            screen_height: height,
+            ..*self
+        }
+    }
+
+    // Width of one screen pixel in the complex plane.
+    pub fn pixel_size(&self) -> f64 {
+        (self.x_stop - self.x_start) / self.screen_width as f64
+    }
+
+    // Maps a screen position in pixels to the point of the complex plane under it.
+    pub fn to_world(&self, x: f64, y: f64) -> Complex {
+        Complex {
+            real: self.x_start + x / self.screen_width as f64 * (self.x_stop - self.x_start),
+            imag: self.y_start + y / self.screen_height as f64 * (self.y_stop - self.y_start),
+        }
+    }
+}
+
+impl From<(f64, f64, f64, f64, f64)> for ScreenInfo {
+    fn from(values: (f64, f64, f64, f64, f64)) -> Self {
+        let (x_start, x_stop, y_start, y_stop, pixels_per_cm) = values;
+
+        ScreenInfo {
+            x_start,
+            x_stop,
+            y_start,
+            y_stop,
+            pixels_per_cm,
+
+            screen_width: (pixels_per_cm * (x_stop - x_start)) as i32,
+            screen_height: (pixels_per_cm * (y_stop - y_start)) as i32,
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::complex::Complex;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TrapShape {
+    #[default]
+    Point,
+    Line,
+    Cross,
+    Circle,
+}
+
+// An orbit trap: the renderer records how close each orbit comes to it. `angle` (degrees) turns
+// lines and crosses, `radius` sizes the circle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trap {
+    pub shape: TrapShape,
+    pub center: Complex,
+    pub angle: f64,
+    pub radius: f64,
+}
+
+impl Default for Trap {
+    fn default() -> Self {
+        Trap {
+            shape: TrapShape::Point,
+            center: Complex::new(0.0, 0.0),
+            angle: 0.0,
+            radius: 0.5,
+        }
+    }
+}
+
+impl Trap {
+    pub fn distance(&self, z: Complex) -> f64 {
+        let dx = z.real - self.center.real;
+        let dy = z.imag - self.center.imag;
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+
+        match self.shape {
+            TrapShape::Point => dx.hypot(dy),
+            TrapShape::Line => (dx * sin - dy * cos).abs(),
+            TrapShape::Cross => (dx * sin - dy * cos).abs().min((dx * cos + dy * sin).abs()),
+            TrapShape::Circle => (dx.hypot(dy) - self.radius).abs(),
+        }
+    }
+
+    pub fn next_shape(self) -> Trap {
+        let shape = match self.shape {
+            TrapShape::Point => TrapShape::Line,
+            TrapShape::Line => TrapShape::Cross,
+            TrapShape::Cross => TrapShape::Circle,
+            TrapShape::Circle => TrapShape::Point,
+        };
+        Trap { shape, ..self }
+    }
+}
+
+// `point:x,y`, `line:x,y,angle`, `cross:x,y,angle` or `circle:x,y,radius`.
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Complex { real, imag } = self.center;
+        match self.shape {
+            TrapShape::Point => write!(f, "point:{:?},{:?}", real, imag),
+            TrapShape::Line => write!(f, "line:{:?},{:?},{:?}", real, imag, self.angle),
+            TrapShape::Cross => write!(f, "cross:{:?},{:?},{:?}", real, imag, self.angle),
+            TrapShape::Circle => write!(f, "circle:{:?},{:?},{:?}", real, imag, self.radius),
+        }
+    }
+}
+
+impl FromStr for Trap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (shape, values) = s.trim().split_once(':').unwrap_or((s.trim(), ""));
+        let values = values
+            .split(',')
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.trim().parse().map_err(|_| format!("invalid number `{}` in trap `{}`", v, s)))
+            .collect::<Result<Vec<f64>, String>>()?;
+
+        let shape = match shape {
+            "point" => TrapShape::Point,
+            "line" => TrapShape::Line,
+            "cross" => TrapShape::Cross,
+            "circle" => TrapShape::Circle,
+            _ => return Err(format!("unknown trap `{}` (expected point, line, cross or circle)", shape)),
+        };
+
+        let max = if shape == TrapShape::Point { 2 } else { 3 };
+        if values.len() > max || values.len() == 1 {
+            return Err(format!("trap `{}` takes x,y{}", s, if max == 3 { " and an optional third value" } else { "" }));
+        }
+
+        let mut trap = Trap { shape, ..Trap::default() };
+        if let [x, y, ..] = values[..] {
+            trap.center = Complex::new(x, y);
+        }
+        if let Some(&third) = values.get(2) {
+            match shape {
+                TrapShape::Circle => trap.radius = third,
+                _ => trap.angle = third,
+            }
+        }
+        Ok(trap)
+    }
+}